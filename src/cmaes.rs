@@ -0,0 +1,251 @@
+use crate::random;
+
+// Covariance matrix adaptation evolution strategy over a flat parameter vector.
+// Follows the (mu/mu_w, lambda) CMA-ES of Hansen's tutorial. Scores are minimized.
+pub struct CmaEs {
+    dimension: usize,
+    population_size: usize,
+    weights: Vec<f64>,
+    mueff: f64,
+    cc: f64,
+    cs: f64,
+    c1: f64,
+    cmu: f64,
+    damps: f64,
+    chi_n: f64,
+
+    pub mean: Vec<f64>,
+    pub sigma: f64,
+    pc: Vec<f64>,
+    ps: Vec<f64>,
+    covariance: Vec<Vec<f64>>,
+    // Columns are the eigenvectors of the covariance matrix
+    eigenvectors: Vec<Vec<f64>>,
+    eigenvalues_sqrt: Vec<f64>,
+
+    generation: usize,
+    evaluations: usize,
+    eigen_evaluations: usize,
+}
+
+fn identity(n: usize) -> Vec<Vec<f64>> {
+    (0..n).map(|i| (0..n).map(|j| if i == j { 1.0 } else { 0.0 }).collect()).collect()
+}
+
+fn norm(v: &[f64]) -> f64 {
+    v.iter().map(|x| x * x).sum::<f64>().sqrt()
+}
+
+// Eigen decomposition of a symmetric matrix with cyclic Jacobi rotations.
+// Returns (eigenvalues, eigenvectors as columns).
+fn symmetric_eigen(matrix: &[Vec<f64>]) -> (Vec<f64>, Vec<Vec<f64>>) {
+    let n = matrix.len();
+    let mut a: Vec<Vec<f64>> = matrix.to_vec();
+    let mut v = identity(n);
+
+    for _sweep in 0..100 {
+        let off_diagonal: f64 = (0..n).flat_map(|i| (0..n).map(move |j| (i, j)))
+            .filter(|(i, j)| i != j)
+            .map(|(i, j)| a[i][j] * a[i][j])
+            .sum();
+        if off_diagonal < 1e-22 {
+            break;
+        }
+
+        for p in 0..n {
+            for q in (p + 1)..n {
+                if a[p][q].abs() < 1e-300 {
+                    continue;
+                }
+                let theta = (a[q][q] - a[p][p]) / (2.0 * a[p][q]);
+                let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+                let c = 1.0 / (t * t + 1.0).sqrt();
+                let s = t * c;
+
+                for row in a.iter_mut() {
+                    let akp = row[p];
+                    let akq = row[q];
+                    row[p] = c * akp - s * akq;
+                    row[q] = s * akp + c * akq;
+                }
+                let row_p = a[p].clone();
+                let row_q = a[q].clone();
+                for k in 0..n {
+                    a[p][k] = c * row_p[k] - s * row_q[k];
+                    a[q][k] = s * row_p[k] + c * row_q[k];
+                }
+                for row in v.iter_mut() {
+                    let vkp = row[p];
+                    let vkq = row[q];
+                    row[p] = c * vkp - s * vkq;
+                    row[q] = s * vkp + c * vkq;
+                }
+            }
+        }
+    }
+
+    ((0..n).map(|i| a[i][i]).collect(), v)
+}
+
+impl CmaEs {
+    pub fn new(initial_mean: &[f32], sigma: f32, population_size: usize) -> CmaEs {
+        let n = initial_mean.len();
+        let nf = n as f64;
+        let mu = population_size / 2;
+
+        let raw_weights: Vec<f64> = (0..mu).map(|i| (mu as f64 + 0.5).ln() - ((i + 1) as f64).ln()).collect();
+        let weight_sum: f64 = raw_weights.iter().sum();
+        let weights: Vec<f64> = raw_weights.iter().map(|w| w / weight_sum).collect();
+        let mueff = 1.0 / weights.iter().map(|w| w * w).sum::<f64>();
+
+        let cc = (4.0 + mueff / nf) / (nf + 4.0 + 2.0 * mueff / nf);
+        let cs = (mueff + 2.0) / (nf + mueff + 5.0);
+        let c1 = 2.0 / ((nf + 1.3).powi(2) + mueff);
+        let cmu = (1.0 - c1).min(2.0 * (mueff - 2.0 + 1.0 / mueff) / ((nf + 2.0).powi(2) + mueff));
+        let damps = 1.0 + 2.0 * (((mueff - 1.0) / (nf + 1.0)).sqrt() - 1.0).max(0.0) + cs;
+        let chi_n = nf.sqrt() * (1.0 - 1.0 / (4.0 * nf) + 1.0 / (21.0 * nf * nf));
+
+        CmaEs {
+            dimension: n,
+            population_size,
+            weights,
+            mueff,
+            cc,
+            cs,
+            c1,
+            cmu,
+            damps,
+            chi_n,
+            mean: initial_mean.iter().map(|x| *x as f64).collect(),
+            sigma: sigma as f64,
+            pc: vec![0.0; n],
+            ps: vec![0.0; n],
+            covariance: identity(n),
+            eigenvectors: identity(n),
+            eigenvalues_sqrt: vec![1.0; n],
+            generation: 0,
+            evaluations: 0,
+            eigen_evaluations: 0,
+        }
+    }
+
+    // Samples a new population: x = mean + sigma * B * D * z
+    pub fn ask(&self) -> Vec<Vec<f32>> {
+        let mut rng = rand::thread_rng();
+        let n = self.dimension;
+        (0..self.population_size).map(|_| {
+            let dz: Vec<f64> = (0..n).map(|i| self.eigenvalues_sqrt[i] * random::normal(&mut rng) as f64).collect();
+            (0..n).map(|i| {
+                let y: f64 = (0..n).map(|j| self.eigenvectors[i][j] * dz[j]).sum();
+                (self.mean[i] + self.sigma * y) as f32
+            }).collect()
+        }).collect()
+    }
+
+    // Updates mean, evolution paths, covariance and step size from scored samples
+    pub fn tell(&mut self, samples: &[Vec<f32>], scores: &[f32]) {
+        let n = self.dimension;
+        let mut order: Vec<usize> = (0..samples.len()).collect();
+        order.sort_by(|a, b| scores[*a].partial_cmp(&scores[*b]).unwrap());
+        let mu = self.weights.len().min(samples.len());
+
+        let old_mean = self.mean.clone();
+        let steps: Vec<Vec<f64>> = order[..mu].iter()
+            .map(|&k| (0..n).map(|i| (samples[k][i] as f64 - old_mean[i]) / self.sigma).collect())
+            .collect();
+        let mut y_w = vec![0.0; n];
+        for (weight, step) in self.weights.iter().zip(&steps) {
+            for i in 0..n {
+                y_w[i] += weight * step[i];
+            }
+        }
+        for i in 0..n {
+            self.mean[i] = old_mean[i] + self.sigma * y_w[i];
+        }
+
+        // C^-1/2 * y_w = B * D^-1 * B^T * y_w
+        let bt_y: Vec<f64> = (0..n).map(|j| (0..n).map(|i| self.eigenvectors[i][j] * y_w[i]).sum::<f64>() / self.eigenvalues_sqrt[j]).collect();
+        let whitened: Vec<f64> = (0..n).map(|i| (0..n).map(|j| self.eigenvectors[i][j] * bt_y[j]).sum()).collect();
+
+        let cs_factor = (self.cs * (2.0 - self.cs) * self.mueff).sqrt();
+        for (p, w) in self.ps.iter_mut().zip(&whitened) {
+            *p = (1.0 - self.cs) * *p + cs_factor * w;
+        }
+
+        self.generation += 1;
+        self.evaluations += samples.len();
+        let ps_norm = norm(&self.ps);
+        let hsig = ps_norm / (1.0 - (1.0 - self.cs).powi(2 * self.generation as i32)).sqrt() / self.chi_n
+            < 1.4 + 2.0 / (n as f64 + 1.0);
+        let hsig = if hsig { 1.0 } else { 0.0 };
+
+        let cc_factor = (self.cc * (2.0 - self.cc) * self.mueff).sqrt();
+        for (p, y) in self.pc.iter_mut().zip(&y_w) {
+            *p = (1.0 - self.cc) * *p + hsig * cc_factor * y;
+        }
+
+        // Rank one and rank mu updates
+        let decay = 1.0 - self.c1 - self.cmu + (1.0 - hsig) * self.c1 * self.cc * (2.0 - self.cc);
+        for i in 0..n {
+            for j in 0..=i {
+                let rank_mu: f64 = self.weights.iter().zip(&steps).map(|(w, step)| w * step[i] * step[j]).sum();
+                let value = decay * self.covariance[i][j] + self.c1 * self.pc[i] * self.pc[j] + self.cmu * rank_mu;
+                self.covariance[i][j] = value;
+                self.covariance[j][i] = value;
+            }
+        }
+
+        self.sigma *= ((self.cs / self.damps) * (ps_norm / self.chi_n - 1.0)).exp();
+
+        // The decomposition is only refreshed every few generations, it is O(n^3)
+        let gap = self.population_size as f64 / (self.c1 + self.cmu) / n as f64 / 10.0;
+        if (self.evaluations - self.eigen_evaluations) as f64 > gap {
+            self.eigen_evaluations = self.evaluations;
+            let (eigenvalues, eigenvectors) = symmetric_eigen(&self.covariance);
+            self.eigenvalues_sqrt = eigenvalues.iter().map(|value| value.max(1e-20).sqrt()).collect();
+            self.eigenvectors = eigenvectors;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn eigenvectors_are_orthonormal_and_scaled_by_their_eigenvalues() {
+        let matrix = vec![
+            vec![4.0, 1.0, -2.0, 0.5],
+            vec![1.0, 3.0, 0.0, 1.5],
+            vec![-2.0, 0.0, 5.0, -1.0],
+            vec![0.5, 1.5, -1.0, 2.0],
+        ];
+        let (eigenvalues, eigenvectors) = symmetric_eigen(&matrix);
+        let n = matrix.len();
+        for k in 0..n {
+            // A v = lambda v for column k
+            for i in 0..n {
+                let av: f64 = (0..n).map(|j| matrix[i][j] * eigenvectors[j][k]).sum();
+                assert!((av - eigenvalues[k] * eigenvectors[i][k]).abs() < 1e-9, "eigenpair {}", k);
+            }
+            for l in 0..n {
+                let dot: f64 = (0..n).map(|i| eigenvectors[i][k] * eigenvectors[i][l]).sum();
+                let expected = if k == l { 1.0 } else { 0.0 };
+                assert!((dot - expected).abs() < 1e-9, "columns {} and {}", k, l);
+            }
+        }
+    }
+
+    #[test]
+    fn minimizes_a_sphere() {
+        let sphere = |x: &[f32]| x.iter().map(|x| x * x).sum::<f32>();
+        let mut strategy = CmaEs::new(&[3.0, -2.0, 1.0, 4.0, -1.0], 1.0, 12);
+        for _ in 0..150 {
+            let samples = strategy.ask();
+            let scores: Vec<f32> = samples.iter().map(|sample| sphere(sample)).collect();
+            strategy.tell(&samples, &scores);
+        }
+        let mean: Vec<f32> = strategy.mean.iter().map(|x| *x as f32).collect();
+        assert!(sphere(&mean) < 1e-6, "mean {:?}", mean);
+    }
+}
@@ -0,0 +1,104 @@
+// Fuel tank of a ship. Thrust forces are fixed, so the ship accelerates harder as it
+// gets lighter, and the motors stop when the tank is empty.
+#[derive(Debug, Clone)]
+pub struct FuelConfig {
+    pub enabled: bool,
+    // Mass of the ship without fuel, in kg
+    pub dry_mass: f32,
+    // Fuel at the start of an episode, in kg
+    pub capacity: f32,
+    // Fuel a motor burns per second at full throttle, in kg/s
+    pub burn_rate: f32,
+    // Added to the score per kg of fuel burned
+    pub score_weight: f32,
+}
+
+impl Default for FuelConfig {
+    fn default() -> FuelConfig {
+        FuelConfig {
+            enabled: false,
+            dry_mass: 0.6,
+            capacity: 0.4,
+            burn_rate: 0.02,
+            score_weight: 0.,
+        }
+    }
+}
+
+impl FuelConfig {
+    pub fn remaining(&self, used: f32) -> f32 {
+        if !self.enabled {
+            return self.capacity;
+        }
+        (self.capacity - used).max(0.)
+    }
+
+    pub fn is_empty(&self, used: f32) -> bool {
+        self.enabled && self.remaining(used) <= 0.
+    }
+
+    // Between 0 and 1, given to the network
+    pub fn remaining_fraction(&self, used: f32) -> f32 {
+        if self.capacity <= 0. {
+            return 0.;
+        }
+        self.remaining(used) / self.capacity
+    }
+
+    // Thrust acceleration is given for a full tank, a lighter ship gets more out of it
+    pub fn thrust_scale(&self, used: f32) -> f32 {
+        if !self.enabled {
+            return 1.;
+        }
+        (self.dry_mass + self.capacity) / (self.dry_mass + self.remaining(used))
+    }
+
+    pub fn burned(&self, throttle_sum: f32, dt: f32) -> f32 {
+        if !self.enabled {
+            return 0.;
+        }
+        throttle_sum * self.burn_rate * dt
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tank() -> FuelConfig {
+        FuelConfig { enabled: true, ..FuelConfig::default() }
+    }
+
+    #[test]
+    fn thrust_grows_with_the_mass_ratio() {
+        let fuel = tank();
+        assert_eq!(fuel.thrust_scale(0.), 1.);
+        // Half the fuel gone: full mass 1 over 0.8
+        assert!((fuel.thrust_scale(0.2) - 1. / 0.8).abs() < 1e-6);
+        // Burning past empty does not lighten the ship any further
+        assert_eq!(fuel.thrust_scale(0.4), fuel.thrust_scale(1.));
+        assert_eq!(FuelConfig::default().thrust_scale(0.3), 1.);
+    }
+
+    #[test]
+    fn burn_is_rate_times_throttle_times_time() {
+        let fuel = tank();
+        // Two motors at 0.75 for half a second
+        assert!((fuel.burned(1.5, 0.5) - 0.02 * 1.5 * 0.5).abs() < 1e-7);
+        assert_eq!(fuel.burned(0., 0.5), 0.);
+        assert_eq!(FuelConfig::default().burned(2., 1.), 0.);
+    }
+
+    #[test]
+    fn tank_runs_dry_at_its_capacity() {
+        let fuel = tank();
+        assert!(!fuel.is_empty(0.39));
+        assert!(fuel.is_empty(0.4));
+        assert!(fuel.is_empty(0.5));
+        assert_eq!(fuel.remaining_fraction(0.1), 0.75);
+        assert_eq!(fuel.remaining_fraction(0.5), 0.);
+        // Without fuel simulation the tank never empties
+        assert!(!FuelConfig::default().is_empty(10.));
+        assert_eq!(FuelConfig::default().remaining_fraction(10.), 1.);
+    }
+}
@@ -0,0 +1,157 @@
+use std::fs;
+
+pub struct Entry<T> {
+    pub member: T,
+    // Average of every score the member got, per second of the episode
+    pub score: f32,
+    pub evaluations: u32,
+    pub generation: i32,
+}
+
+// Best members seen over all generations. Members that show up again (elites survive
+// between generations) or get re-evaluated have their scores averaged, so a single
+// lucky evaluation does not keep a member in the hall forever.
+pub struct HallOfFame<T> {
+    pub entries: Vec<Entry<T>>,
+    pub capacity: usize,
+    // Re-evaluate the members every this many generations
+    pub reevaluate_interval: i32,
+    // How many members are copied back into the population each generation
+    pub reinject_count: usize,
+}
+
+impl<T: Clone> HallOfFame<T> {
+    pub fn new(capacity: usize, reevaluate_interval: i32, reinject_count: usize) -> HallOfFame<T> {
+        HallOfFame {
+            entries: vec![],
+            capacity,
+            reevaluate_interval,
+            reinject_count,
+        }
+    }
+
+    fn sort(&mut self) {
+        self.entries.sort_by(|a, b| a.score.partial_cmp(&b.score).unwrap());
+    }
+
+    fn add_score(entry: &mut Entry<T>, score: f32) {
+        entry.score = (entry.score * entry.evaluations as f32 + score) / (entry.evaluations + 1) as f32;
+        entry.evaluations += 1;
+    }
+
+    // `same` tells whether two members have the same controller
+    pub fn consider<F: Fn(&T, &T) -> bool>(&mut self, member: &T, score: f32, generation: i32, same: F) {
+        if let Some(entry) = self.entries.iter_mut().find(|entry| same(&entry.member, member)) {
+            HallOfFame::add_score(entry, score);
+        } else if self.entries.len() < self.capacity || score < self.entries[self.entries.len() - 1].score {
+            self.entries.push(Entry {
+                member: member.clone(),
+                score,
+                evaluations: 1,
+                generation,
+            });
+        }
+        self.sort();
+        self.entries.truncate(self.capacity);
+    }
+
+    pub fn should_reevaluate(&self, generation: i32) -> bool {
+        self.reevaluate_interval > 0 && generation > 0 && generation % self.reevaluate_interval == 0
+    }
+
+    // Scores must be in the same order as `entries`
+    pub fn add_reevaluation_scores(&mut self, scores: &[f32]) {
+        for (entry, score) in self.entries.iter_mut().zip(scores) {
+            HallOfFame::add_score(entry, *score);
+        }
+        self.sort();
+    }
+
+    pub fn champions(&self, count: usize) -> Vec<T> {
+        self.entries.iter().take(count).map(|entry| entry.member.clone()).collect()
+    }
+
+    // One header line per member (score, evaluations, generation) followed by the text of the member
+    pub fn save<F: Fn(&T) -> String>(&self, path: &str, to_text: F) -> std::io::Result<()> {
+        let mut text = String::new();
+        for entry in &self.entries {
+            text.push_str(&format!("member {} {} {}\n", entry.score, entry.evaluations, entry.generation));
+            text.push_str(&to_text(&entry.member));
+            text.push('\n');
+        }
+        fs::write(path, text)
+    }
+
+    pub fn load<F: Fn(&str) -> Option<T>>(path: &str, capacity: usize, from_text: F) -> std::io::Result<HallOfFame<T>> {
+        let text = fs::read_to_string(path)?;
+        let mut hall_of_fame = HallOfFame::new(capacity, 0, 0);
+        for block in text.split("member ").filter(|block| !block.trim().is_empty()) {
+            let (header, member_text) = match block.split_once('\n') {
+                Some(parts) => parts,
+                None => continue,
+            };
+            let values: Vec<&str> = header.split_whitespace().collect();
+            if values.len() < 3 {
+                continue;
+            }
+            if let (Ok(score), Ok(evaluations), Ok(generation), Some(member)) =
+                (values[0].parse(), values[1].parse(), values[2].parse(), from_text(member_text))
+            {
+                hall_of_fame.entries.push(Entry { member, score, evaluations, generation });
+            }
+        }
+        hall_of_fame.sort();
+        hall_of_fame.entries.truncate(capacity);
+        Ok(hall_of_fame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn same(a: &String, b: &String) -> bool {
+        a == b
+    }
+
+    #[test]
+    fn repeated_members_have_their_scores_averaged() {
+        let mut hall_of_fame = HallOfFame::new(3, 0, 1);
+        hall_of_fame.consider(&"lucky".to_string(), 1., 0, same);
+        hall_of_fame.consider(&"steady".to_string(), 2., 0, same);
+        hall_of_fame.consider(&"lucky".to_string(), 5., 1, same);
+        assert_eq!(hall_of_fame.entries.len(), 2);
+        assert_eq!(hall_of_fame.entries[0].member, "steady");
+        let lucky = &hall_of_fame.entries[1];
+        assert_eq!((lucky.score, lucky.evaluations, lucky.generation), (3., 2, 0));
+    }
+
+    #[test]
+    fn full_hall_keeps_only_the_best() {
+        let mut hall_of_fame = HallOfFame::new(2, 0, 1);
+        for (name, score) in [("a", 3.), ("b", 1.), ("c", 2.), ("d", 4.)] {
+            hall_of_fame.consider(&name.to_string(), score, 0, same);
+        }
+        assert_eq!(hall_of_fame.champions(5), vec!["b".to_string(), "c".to_string()]);
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let mut hall_of_fame = HallOfFame::new(3, 0, 1);
+        hall_of_fame.consider(&"first\nline two".to_string(), 0.5, 4, same);
+        hall_of_fame.consider(&"second".to_string(), 1.5, 7, same);
+        let path = std::env::temp_dir().join(format!("hall_of_fame_test_{}.txt", std::process::id()));
+        let path = path.to_str().unwrap();
+        hall_of_fame.save(path, |member| member.clone()).unwrap();
+
+        let loaded = HallOfFame::load(path, 3, |text| Some(text.trim_end().to_string())).unwrap();
+        let _ = fs::remove_file(path);
+        let summary: Vec<(String, f32, u32, i32)> = loaded.entries.iter()
+            .map(|entry| (entry.member.clone(), entry.score, entry.evaluations, entry.generation))
+            .collect();
+        assert_eq!(summary, vec![
+            ("first\nline two".to_string(), 0.5, 1, 4),
+            ("second".to_string(), 1.5, 1, 7),
+        ]);
+    }
+}
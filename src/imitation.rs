@@ -0,0 +1,211 @@
+use std::fs;
+use rand::seq::SliceRandom;
+use crate::motor::MotorConfig;
+use crate::neural::{neural_net, GradientDescent, Optimizer};
+use crate::observation::Normalizer;
+use crate::physics::PhysicsConfig;
+use crate::point::{Vector, wrap_angle};
+use crate::ship_design::ShipDesign;
+
+// True state of a ship, what the pilots fly by
+#[derive(Debug, Clone)]
+pub struct FlightState {
+    // From the ship center to the goal
+    pub to_goal: Vector,
+    pub velocity: Vector,
+    // Turn of the ship from its design orientation, see `Ship::tilt`, and how fast it changes
+    pub tilt: f32,
+    pub tilt_rate: f32,
+}
+
+// Arrow keys of the human pilot
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PilotKeys {
+    pub up: bool,
+    pub down: bool,
+    pub left: bool,
+    pub right: bool,
+}
+
+// Who picks the network outputs of a ship
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Pilot {
+    #[default]
+    Network,
+    // Hand tuned controller flying towards the goal
+    Baseline,
+    // The arrow keys ask for an acceleration, the attitude is held level for the pilot
+    Human(PilotKeys),
+}
+
+// Acceleration the baseline asks for at most, in m/s^2
+const BASELINE_MAX_ACCELERATION: f32 = 6.;
+// Acceleration a held arrow key asks for
+const HUMAN_ACCELERATION: f32 = 4.;
+
+impl Pilot {
+    // Network outputs in [0, 1]: all throttles first, then all gimbal angles
+    pub fn outputs(&self, state: &FlightState, design: &ShipDesign, motors: &MotorConfig, physics: &PhysicsConfig) -> Option<Vec<f32>> {
+        let acceleration = match self {
+            Pilot::Network => return None,
+            Pilot::Baseline => {
+                let wanted = state.to_goal.multiplied(4.).subtracted(&state.velocity.multiplied(3.));
+                let length = wanted.length();
+                if length > BASELINE_MAX_ACCELERATION {
+                    wanted.multiplied(BASELINE_MAX_ACCELERATION / length)
+                } else {
+                    wanted
+                }
+            },
+            Pilot::Human(keys) => {
+                let axis = |negative: bool, positive: bool| (positive as i32 - negative as i32) as f32;
+                // y points down
+                Vector::new(axis(keys.left, keys.right), axis(keys.up, keys.down))
+                    .multiplied(HUMAN_ACCELERATION)
+                    .subtracted(&state.velocity.multiplied(0.5))
+            },
+        };
+        Some(allocate(&acceleration, state, design, motors, physics))
+    }
+}
+
+// Turns a wanted acceleration of the ship center into throttles and gimbal angles. The
+// gimbals point the thrust, differential throttle along the ship axis keeps it level, so
+// designs with thrusters spread along the axis fly best.
+fn allocate(acceleration: &Vector, state: &FlightState, design: &ShipDesign, motors: &MotorConfig, physics: &PhysicsConfig) -> Vec<f32> {
+    let thrust = acceleration.subtracted(&Vector::new(0., physics.gravity));
+    let max_thrust: f32 = design.thrusters.iter().map(|thruster| thruster.max_thrust).sum();
+    // Every point gets the acceleration of the motors on it, the center gets the average
+    let full_throttle = physics.thrust_acceleration * max_thrust / design.points.len() as f32;
+    let collective = thrust.length() / full_throttle;
+
+    // A motor at gimbal angle a pushes along (sin(a + tilt), -cos(a + tilt)), see `Ship::motor_direction`
+    let thrust_angle = thrust.x.atan2(-thrust.y);
+    let levelling = 1.5 * state.tilt + 0.4 * state.tilt_rate;
+    let local_points = design.local_points();
+
+    let mut throttles = vec![];
+    let mut angles = vec![];
+    for thruster in &design.thrusters {
+        // Pushing harder on the side of the first point lowers the tilt
+        let lever = local_points[thruster.point].x;
+        throttles.push((collective + levelling * lever).clamp(0., 1.));
+        let range = motors.gimbal_range.min(thruster.gimbal_range);
+        let angle = wrap_angle(thrust_angle - state.tilt).clamp(-range, range);
+        // Inverse of `MotorConfig::angle_command`
+        angles.push(if range > 0. { angle / (2. * range) + 0.5 } else { 0.5 });
+    }
+    throttles.extend(angles);
+    throttles
+}
+
+// Network inputs and the outputs a pilot chose for them
+#[derive(Debug, Clone, Default)]
+pub struct Demonstrations {
+    pub inputs: Vec<Vec<f32>>,
+    pub outputs: Vec<Vec<f32>>,
+}
+
+impl Demonstrations {
+    pub fn push(&mut self, inputs: Vec<f32>, outputs: Vec<f32>) {
+        self.inputs.push(inputs);
+        self.outputs.push(outputs);
+    }
+
+    pub fn len(&self) -> usize {
+        self.inputs.len()
+    }
+
+    pub fn extend(&mut self, other: Demonstrations) {
+        self.inputs.extend(other.inputs);
+        self.outputs.extend(other.outputs);
+    }
+
+    // One sample per line, "<inputs> | <outputs>", followed by the input statistics the
+    // inputs were normalized with
+    pub fn save(&self, path: &str, normalizer: &Normalizer) -> std::io::Result<()> {
+        let join = |values: &[f32]| values.iter().map(|value| value.to_string()).collect::<Vec<String>>().join(" ");
+        let mut text = String::new();
+        for (inputs, outputs) in self.inputs.iter().zip(&self.outputs) {
+            text.push_str(&format!("{} | {}\n", join(inputs), join(outputs)));
+        }
+        text.push_str(&normalizer.to_text());
+        text.push('\n');
+        fs::write(path, text)
+    }
+
+    // Lines that cannot be read are skipped
+    pub fn load(path: &str) -> std::io::Result<(Demonstrations, Normalizer)> {
+        let text = fs::read_to_string(path)?;
+        let parse = |values: &str| values.split_whitespace().map(|value| value.parse().ok()).collect::<Option<Vec<f32>>>();
+        let mut demonstrations = Demonstrations::default();
+        for line in text.lines() {
+            if let Some((inputs, outputs)) = line.split_once('|') {
+                if let (Some(inputs), Some(outputs)) = (parse(inputs), parse(outputs)) {
+                    demonstrations.push(inputs, outputs);
+                }
+            }
+        }
+        Ok((demonstrations, Normalizer::from_text(&text).unwrap_or_default()))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PretrainConfig {
+    // Episodes of the baseline pilot recorded when there is no demonstration file
+    pub baseline_episodes: usize,
+    pub epochs: usize,
+    pub batch_size: usize,
+    pub optimizer: Optimizer,
+}
+
+impl Default for PretrainConfig {
+    fn default() -> PretrainConfig {
+        PretrainConfig {
+            baseline_episodes: 20,
+            epochs: 40,
+            batch_size: 32,
+            optimizer: Optimizer::adam(0.01),
+        }
+    }
+}
+
+// Fits the network to the demonstrations by minibatch gradient descent on the mean squared
+// error. Returns the mean loss of the last epoch, None when the demonstrations do not fit
+// the network.
+pub fn train(net: &mut neural_net, demonstrations: &Demonstrations, config: &PretrainConfig) -> Option<f32> {
+    let fits = demonstrations.len() > 0
+        && demonstrations.inputs.iter().all(|inputs| inputs.len() == net.input_size())
+        && demonstrations.outputs.iter().all(|outputs| outputs.len() == net.output_size());
+    if !fits {
+        return None;
+    }
+    let mut rng = rand::thread_rng();
+    let mut weights = net.get_weights_flat();
+    let mut descent = GradientDescent::new(config.optimizer.clone(), weights.len());
+
+    let mut order: Vec<usize> = (0..demonstrations.len()).collect();
+    let mut epoch_loss = 0.;
+    for epoch in 0..config.epochs {
+        order.shuffle(&mut rng);
+        epoch_loss = 0.;
+        for batch in order.chunks(config.batch_size.max(1)) {
+            let mut gradient = vec![0.; weights.len()];
+            for &sample in batch {
+                let (loss, sample_gradient) = net.mse_gradient(&demonstrations.inputs[sample], &demonstrations.outputs[sample]);
+                epoch_loss += loss;
+                for (total, value) in gradient.iter_mut().zip(sample_gradient) {
+                    *total += value / batch.len() as f32;
+                }
+            }
+
+            descent.step(&mut weights, &gradient);
+            net.set_weights_flat(&weights);
+        }
+        epoch_loss /= demonstrations.len() as f32;
+        if epoch % 10 == 0 || epoch + 1 == config.epochs {
+            println!("Pretraining epoch {}: loss {}", epoch, epoch_loss);
+        }
+    }
+    Some(epoch_loss)
+}
@@ -0,0 +1,172 @@
+use crate::point::Vector;
+
+#[derive(Debug, Clone)]
+pub struct PointState {
+    pub position: Vector,
+    pub velocity: Vector,
+}
+
+// Acceleration of a point given its position and velocity
+pub type Acceleration<'a> = dyn Fn(&Vector, &Vector) -> Vector + 'a;
+
+pub trait Integrator {
+    fn step(&self, state: &PointState, acceleration: &Acceleration, dt: f32) -> PointState;
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum IntegratorKind {
+    // With the velocity implied by the last position, the position Verlet step the ship
+    // used (x' = 2x - x_last + a dt^2) is exactly this method
+    SemiImplicitEuler,
+    VelocityVerlet,
+    Rk4,
+}
+
+impl IntegratorKind {
+    pub fn integrator(&self) -> &'static dyn Integrator {
+        match self {
+            IntegratorKind::SemiImplicitEuler => &SemiImplicitEuler,
+            IntegratorKind::VelocityVerlet => &VelocityVerlet,
+            IntegratorKind::Rk4 => &Rk4,
+        }
+    }
+}
+
+pub struct SemiImplicitEuler;
+
+impl Integrator for SemiImplicitEuler {
+    fn step(&self, state: &PointState, acceleration: &Acceleration, dt: f32) -> PointState {
+        let a = acceleration(&state.position, &state.velocity);
+        let velocity = state.velocity.added(&a.multiplied(dt));
+        PointState {
+            position: state.position.added(&velocity.multiplied(dt)),
+            velocity,
+        }
+    }
+}
+
+pub struct VelocityVerlet;
+
+impl Integrator for VelocityVerlet {
+    fn step(&self, state: &PointState, acceleration: &Acceleration, dt: f32) -> PointState {
+        let a = acceleration(&state.position, &state.velocity);
+        let position = state.position
+            .added(&state.velocity.multiplied(dt))
+            .added(&a.multiplied(0.5 * dt * dt));
+        // Velocity dependent forces use the predicted velocity for the second evaluation
+        let predicted_velocity = state.velocity.added(&a.multiplied(dt));
+        let a_next = acceleration(&position, &predicted_velocity);
+        PointState {
+            position,
+            velocity: state.velocity.added(&a.added(&a_next).multiplied(0.5 * dt)),
+        }
+    }
+}
+
+pub struct Rk4;
+
+impl Integrator for Rk4 {
+    fn step(&self, state: &PointState, acceleration: &Acceleration, dt: f32) -> PointState {
+        let x = &state.position;
+        let v = &state.velocity;
+
+        let k1_x = v.clone();
+        let k1_v = acceleration(x, v);
+
+        let x2 = x.added(&k1_x.multiplied(dt * 0.5));
+        let v2 = v.added(&k1_v.multiplied(dt * 0.5));
+        let k2_x = v2.clone();
+        let k2_v = acceleration(&x2, &v2);
+
+        let x3 = x.added(&k2_x.multiplied(dt * 0.5));
+        let v3 = v.added(&k2_v.multiplied(dt * 0.5));
+        let k3_x = v3.clone();
+        let k3_v = acceleration(&x3, &v3);
+
+        let x4 = x.added(&k3_x.multiplied(dt));
+        let v4 = v.added(&k3_v.multiplied(dt));
+        let k4_x = v4.clone();
+        let k4_v = acceleration(&x4, &v4);
+
+        let dx = k1_x.added(&k2_x.multiplied(2.)).added(&k3_x.multiplied(2.)).added(&k4_x);
+        let dv = k1_v.added(&k2_v.multiplied(2.)).added(&k3_v.multiplied(2.)).added(&k4_v);
+        PointState {
+            position: x.added(&dx.multiplied(dt / 6.)),
+            velocity: v.added(&dv.multiplied(dt / 6.)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: [IntegratorKind; 3] = [
+        IntegratorKind::SemiImplicitEuler,
+        IntegratorKind::VelocityVerlet,
+        IntegratorKind::Rk4,
+    ];
+
+    fn gravity(_position: &Vector, _velocity: &Vector) -> Vector {
+        Vector::new(0., 9.81)
+    }
+
+    fn run(kind: IntegratorKind, start: PointState, dt: f32, steps: usize) -> PointState {
+        let integrator = kind.integrator();
+        let mut state = start;
+        for _ in 0..steps {
+            state = integrator.step(&state, &gravity, dt);
+        }
+        state
+    }
+
+    // Kinetic plus potential energy per unit mass, y points down
+    fn energy(state: &PointState) -> f32 {
+        0.5 * (state.velocity.x.powi(2) + state.velocity.y.powi(2)) - 9.81 * state.position.y
+    }
+
+    #[test]
+    fn free_fall_energy() {
+        let start = PointState { position: Vector::new(0., 0.), velocity: Vector::new(0., 0.) };
+        for kind in ALL {
+            let end = run(kind, start.clone(), 1. / 120., 240);
+            let drift = (energy(&end) - energy(&start)).abs();
+            // Kinetic energy after two seconds is ~190, semi-implicit Euler is only first order
+            let tolerance = match kind {
+                IntegratorKind::SemiImplicitEuler => 1.0,
+                _ => 0.05,
+            };
+            assert!(drift < tolerance, "{:?} energy drifted by {}", kind, drift);
+        }
+    }
+
+    #[test]
+    fn projectile_trajectory() {
+        let start = PointState { position: Vector::new(1., 2.), velocity: Vector::new(3., -5.) };
+        let t: f32 = 1.5;
+        let expected_x = 1. + 3. * t;
+        let expected_y = 2. - 5. * t + 0.5 * 9.81 * t * t;
+        for kind in ALL {
+            let end = run(kind, start.clone(), 1. / 120., 180);
+            let error = ((end.position.x - expected_x).powi(2) + (end.position.y - expected_y).powi(2)).sqrt();
+            let tolerance = match kind {
+                IntegratorKind::SemiImplicitEuler => 0.1,
+                _ => 1e-3,
+            };
+            assert!(error < tolerance, "{:?} missed the landing point by {}", kind, error);
+            assert!((end.velocity.y - (-5. + 9.81 * t)).abs() < 0.01, "{:?} velocity {}", kind, end.velocity.y);
+        }
+    }
+
+    #[test]
+    fn rk4_handles_velocity_dependent_forces() {
+        // Linear drag: v(t) = v0 * exp(-k t)
+        let drag = |_position: &Vector, velocity: &Vector| velocity.multiplied(-2.);
+        let mut state = PointState { position: Vector::new(0., 0.), velocity: Vector::new(4., 0.) };
+        for _ in 0..120 {
+            state = Rk4.step(&state, &drag, 1. / 120.);
+        }
+        let expected = 4. * (-2.0f32).exp();
+        assert!((state.velocity.x - expected).abs() < 1e-4, "{} vs {}", state.velocity.x, expected);
+    }
+}
@@ -0,0 +1,78 @@
+// Island model: the population is split into sub-populations that evolve on their own
+// and exchange their best members along a ring every few generations.
+pub struct IslandModel {
+    // Mutation rate used on each island
+    pub learning_rates: Vec<f32>,
+    pub migration_interval: u32,
+    pub migrant_count: usize,
+    pub generation: u32,
+}
+
+impl IslandModel {
+    pub fn new(learning_rates: Vec<f32>, migration_interval: u32, migrant_count: usize) -> IslandModel {
+        IslandModel {
+            learning_rates,
+            migration_interval,
+            migrant_count,
+            generation: 0,
+        }
+    }
+
+    pub fn island_count(&self) -> usize {
+        self.learning_rates.len()
+    }
+
+    pub fn is_migration_generation(&self) -> bool {
+        self.migration_interval > 0 && self.generation > 0 && self.generation.is_multiple_of(self.migration_interval)
+    }
+}
+
+// Copies the first `count` members of each island over the last members of the next island.
+// Islands are expected to be ordered best first.
+pub fn migrate<T: Clone>(islands: &mut [Vec<T>], count: usize) {
+    let migrants: Vec<Vec<T>> = islands.iter()
+        .map(|island| island.iter().take(count).cloned().collect())
+        .collect();
+    let island_count = islands.len();
+    for (from, group) in migrants.into_iter().enumerate() {
+        let target = &mut islands[(from + 1) % island_count];
+        let start = target.len().saturating_sub(group.len());
+        for (slot, migrant) in target[start..].iter_mut().zip(group) {
+            *slot = migrant;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn best_of_each_island_replaces_the_worst_of_the_next() {
+        // Islands are sorted best first
+        let mut islands = vec![vec![1, 2, 3], vec![4, 5, 6], vec![7, 8, 9]];
+        migrate(&mut islands, 1);
+        assert_eq!(islands, vec![vec![1, 2, 7], vec![4, 5, 1], vec![7, 8, 4]]);
+    }
+
+    #[test]
+    fn migrants_are_taken_before_any_island_is_overwritten() {
+        let mut islands = vec![vec![1, 2], vec![3, 4]];
+        migrate(&mut islands, 2);
+        assert_eq!(islands, vec![vec![3, 4], vec![1, 2]]);
+    }
+
+    #[test]
+    fn migration_happens_every_interval() {
+        let mut model = IslandModel::new(vec![0.1, 0.2], 3, 1);
+        let migrations: Vec<u32> = (0..10).filter(|&generation| {
+            model.generation = generation;
+            model.is_migration_generation()
+        }).collect();
+        assert_eq!(migrations, vec![3, 6, 9]);
+
+        model.migration_interval = 0;
+        model.generation = 3;
+        assert!(!model.is_migration_generation());
+    }
+}
@@ -4,33 +4,141 @@ use rand::Rng;
 use rayon::prelude::*;
 use crate::neural::neural_net;
 use crate::point::Vector;
+use std::sync::Arc;
 
 mod point;
 mod neural;
+mod pareto;
+mod novelty;
+mod map_elites;
+mod neat;
+mod random;
+mod cmaes;
+mod nes;
+mod metrics;
+mod island;
+mod hall_of_fame;
+mod stopping;
+mod physics;
+mod scenario;
+mod rigid_body;
+mod integrator;
+mod motor;
+mod fuel;
+mod wind;
+mod terrain;
+mod obstacle;
+mod ship_design;
+mod sensor;
+mod randomization;
+mod observation;
+mod imitation;
+mod policy_gradient;
 
 const WIDTH: usize = 1000;
 const HEIGHT: usize = 800;
 
 const ITERATIONS: usize = 500;
 
+const HALL_OF_FAME_PATH: &str = "hall_of_fame.txt";
+const CHAMPION_PATH: &str = "champion.txt";
+// State/action pairs recorded from the human pilot
+const DEMONSTRATIONS_PATH: &str = "demonstrations.txt";
+
+// Deviation of the noise added to the weights when the population is seeded from a champion
+const CHAMPION_SEED_NOISE: f32 = 0.05;
+
+// Landing and navigation score for every control tick after a crash or leaving the world
+const CRASH_COST: f32 = 10.;
+
+enum TrainingMode {
+    // Genetic algorithm with NSGA-II selection
+    Genetic,
+    // Selects for behaviours far from the population and the archive
+    Novelty(novelty::NoveltyArchive),
+    // Keeps the best controller per behaviour cell and breeds from the grid
+    MapElites(map_elites::MapElites),
+    // Evolves the topology of `Ship::genome` instead of the weights of `Ship::neural_net`
+    Neat(neat::NeatPopulation),
+    // Samples the flattened `Ship::neural_net` weights from an adapted gaussian
+    CmaEs(cmaes::CmaEs),
+    // OpenAI style evolution strategy on the flattened weights
+    Nes(nes::Nes),
+    // Sub-populations keyed by `Ship::island`, each with its own mutation rate
+    Islands(island::IslandModel),
+    // Every ship flies the same gaussian policy, which is trained on their rollouts
+    PolicyGradient(policy_gradient::PolicyGradient),
+}
+
+impl TrainingMode {
+    fn name(&self) -> &'static str {
+        match self {
+            TrainingMode::Genetic => "genetic",
+            TrainingMode::Novelty(_) => "novelty",
+            TrainingMode::MapElites(_) => "map-elites",
+            TrainingMode::Neat(_) => "neat",
+            TrainingMode::CmaEs(_) => "cmaes",
+            TrainingMode::Nes(_) => "nes",
+            TrainingMode::Islands(_) => "islands",
+            TrainingMode::PolicyGradient(trainer) if trainer.config.clip.is_none() => "reinforce",
+            TrainingMode::PolicyGradient(_) => "ppo",
+        }
+    }
+
+    // Evolution strategies sample the whole population from their own distribution
+    fn accepts_reinjection(&self) -> bool {
+        !matches!(self, TrainingMode::CmaEs(_) | TrainingMode::Nes(_) | TrainingMode::PolicyGradient(_))
+    }
+}
+
 #[derive(Clone)]
 struct Ship {
-    pos1: point::Vector,
-    pos2: point::Vector,
-    pos_1_last: point::Vector,
-    pos_2_last: point::Vector,
+    design: Arc<ship_design::ShipDesign>,
+    // Ranges the conditions are drawn from on every reset
+    randomization: Arc<randomization::DomainRandomization>,
+    conditions: randomization::EpisodeConditions,
+    // Input statistics of this controller, kept over episodes and passed on to children
+    normalizer: observation::Normalizer,
+    // Positions of the design points, now and one physics step earlier
+    points: Vec<point::Vector>,
+    points_last: Vec<point::Vector>,
     // vel1: point::Vector,
     // vel2: point::Vector,
-    angle1: f32,
-    angle2: f32,
-    throttle1: f32,
-    throttle2: f32,
+    // One per thruster of the design. The commands are the network outputs, the motors
+    // follow them through the actuator model.
+    motors: Vec<motor::MotorState>,
+    // Fuel burned this episode in kg and the resulting thrust multiplier
+    fuel_used: f32,
+    thrust_scale: f32,
+    // Seconds since the start of the episode
+    time: f32,
+    // Bias and delay state of the sensor model
+    sensors: sensor::SensorState,
+    // Histogram of the controller outputs this episode
+    output_usage: metrics::OutputUsage,
 
     best_distance: Option<f32>,
     score: f32,
+    objectives: pareto::Objectives,
+    behaviour: novelty::Behaviour,
     dead: bool,
+    // Set once the ship touches the ground, it stops flying after that
+    touchdown: Option<terrain::Touchdown>,
 
     neural_net: neural_net,
+    // When set, the ship is controlled by this genome instead of neural_net
+    genome: Option<neat::Genome>,
+    // Sub-population the ship belongs to in the island model
+    island: usize,
+    // Flies the ship instead of the network when it is not `Pilot::Network`
+    pilot: imitation::Pilot,
+    // Collects the network inputs and chosen outputs of every control tick when set
+    recording: Option<imitation::Demonstrations>,
+    // When set, the motor commands are sampled around the network outputs and the rollout
+    // is kept for the policy gradient trainer
+    trajectory: Option<policy_gradient::Trajectory>,
+    // State of the rigid body model, the points follow it when it is used
+    body: Option<rigid_body::RigidBody>,
 }
 
 fn world_to_screen(point: point::Vector) -> point::Vector {
@@ -47,8 +155,133 @@ fn screen_to_world(point: point::Vector) -> point::Vector {
     )
 }
 
+// Draws the wind as short lines on a grid, each starting at a dot
+fn draw_wind(dt: &mut DrawTarget, wind: &wind::WindField, time: f32) {
+    if let wind::WindField::Calm = wind {
+        return;
+    }
+    let spacing = 60;
+    let mut pb = PathBuilder::new();
+    for x in (spacing / 2..WIDTH).step_by(spacing) {
+        for y in (spacing / 2..HEIGHT).step_by(spacing) {
+            let start = point::Vector::new(x as f32, y as f32);
+            let velocity = wind.velocity_at(&screen_to_world(start.clone()), time);
+            let end = start.added(&velocity.multiplied(10.));
+            pb.move_to(start.x, start.y);
+            pb.line_to(end.x, end.y);
+            pb.rect(start.x - 1.5, start.y - 1.5, 3., 3.);
+        }
+    }
+    let path = pb.finish();
+    dt.stroke(
+        &path,
+        &Source::Solid(SolidSource::from_unpremultiplied_argb(0xff, 0x22, 0x44, 0x66)),
+        &StrokeStyle{
+            cap: LineCap::Round,
+            join: LineJoin::Round,
+            width: 1.5,
+            miter_limit: 1.,
+            dash_array: vec![],
+            dash_offset: 0.,
+        },
+        &DrawOptions::new()
+    );
+}
+
+fn draw_obstacles(dt: &mut DrawTarget, obstacles: &[obstacle::Obstacle]) {
+    let to_screen = |point: &point::Vector| world_to_screen(point.multiplied(100.));
+    let mut pb = PathBuilder::new();
+    for obstacle in obstacles {
+        match obstacle {
+            obstacle::Obstacle::Circle { center, radius } => {
+                let center = to_screen(center);
+                pb.move_to(center.x + radius * 100., center.y);
+                pb.arc(center.x, center.y, radius * 100., 0., 2. * std::f32::consts::PI);
+                pb.close();
+            },
+            obstacle::Obstacle::Rectangle { min, max } => {
+                let min = to_screen(min);
+                let max = to_screen(max);
+                pb.rect(min.x, min.y, max.x - min.x, max.y - min.y);
+            },
+            obstacle::Obstacle::Polygon(corners) => {
+                for (i, corner) in corners.iter().enumerate() {
+                    let corner = to_screen(corner);
+                    if i == 0 {
+                        pb.move_to(corner.x, corner.y);
+                    } else {
+                        pb.line_to(corner.x, corner.y);
+                    }
+                }
+                pb.close();
+            },
+        }
+    }
+    let path = pb.finish();
+    dt.fill(
+        &path,
+        &Source::Solid(SolidSource::from_unpremultiplied_argb(0xff, 0x44, 0x44, 0x66)),
+        &DrawOptions::new()
+    );
+}
+
+// Fills the ground below the terrain line and marks the landing pads
+fn draw_terrain(dt: &mut DrawTarget, terrain: &terrain::Terrain) {
+    let to_screen = |point: &point::Vector| world_to_screen(point.multiplied(100.));
+    let left = screen_to_world(point::Vector::new(0., 0.)).x;
+    let right = screen_to_world(point::Vector::new(WIDTH as f32, 0.)).x;
+
+    let mut pb = PathBuilder::new();
+    let start = to_screen(&point::Vector::new(left, terrain.height_at(left)));
+    pb.move_to(start.x, HEIGHT as f32);
+    pb.line_to(start.x, start.y);
+    for point in terrain.ground.iter().filter(|point| point.x > left && point.x < right) {
+        let point = to_screen(point);
+        pb.line_to(point.x, point.y);
+    }
+    let end = to_screen(&point::Vector::new(right, terrain.height_at(right)));
+    pb.line_to(end.x, end.y);
+    pb.line_to(end.x, HEIGHT as f32);
+    pb.close();
+    let path = pb.finish();
+    dt.fill(
+        &path,
+        &Source::Solid(SolidSource::from_unpremultiplied_argb(0xff, 0x33, 0x2b, 0x22)),
+        &DrawOptions::new()
+    );
+
+    for (index, pad) in terrain.pads.iter().enumerate() {
+        let pad_left = to_screen(&point::Vector::new(pad.left, terrain.height_at(pad.left)));
+        let pad_right = to_screen(&point::Vector::new(pad.right, terrain.height_at(pad.right)));
+        let mut pb = PathBuilder::new();
+        pb.move_to(pad_left.x, pad_left.y);
+        pb.line_to(pad_right.x, pad_right.y);
+        let path = pb.finish();
+        // The target pad is green, the others yellow
+        let color = if index == terrain.target_pad {
+            SolidSource::from_unpremultiplied_argb(0xff, 0x22, 0xaa, 0x44)
+        } else {
+            SolidSource::from_unpremultiplied_argb(0xff, 0xaa, 0xaa, 0x22)
+        };
+        dt.stroke(
+            &path,
+            &Source::Solid(color),
+            &StrokeStyle{
+                cap: LineCap::Butt,
+                join: LineJoin::Miter,
+                width: 6.,
+                miter_limit: 1.,
+                dash_array: vec![],
+                dash_offset: 0.,
+            },
+            &DrawOptions::new()
+        );
+    }
+}
+
 impl Ship {
-    fn new() -> Ship {
+    fn new(scenario: &scenario::Scenario) -> Ship {
+        let design = &scenario.ship;
         let mut rng = rand::thread_rng();
         // let angle1: f32 = rng.gen::<f32>() - 0.5;
         // let angle2: f32 = rng.gen::<f32>() - 0.5;
@@ -57,19 +290,34 @@ impl Ship {
         // let throttle1: f32 = 0.0;
 
         Ship {
-            pos1: point::Vector::new(0.5, 0.),
-            pos2: point::Vector::new(-0.5, 0.),
-            pos_1_last: point::Vector::new(0.5, 0.),
-            pos_2_last: point::Vector::new(-0.5, 0.),
-            angle1: 0.,
-            angle2: 0.,
-            throttle1: 0.,
-            throttle2: 0.,
+            design: design.clone(),
+            randomization: scenario.randomization.clone(),
+            conditions: randomization::EpisodeConditions::default(),
+            normalizer: observation::Normalizer::default(),
+            points: design.points.clone(),
+            points_last: design.points.clone(),
+            motors: vec![motor::MotorState::default(); design.thrusters.len()],
+            fuel_used: 0.,
+            thrust_scale: 1.,
+            time: 0.,
+            sensors: sensor::SensorState::default(),
+            output_usage: metrics::OutputUsage::default(),
             score: 0.,
+            objectives: pareto::Objectives::default(),
+            behaviour: novelty::Behaviour::default(),
             best_distance: None,
             dead: false,
-            // ship_angle, x_dist, y_dist, ship_angle_velocity, ship_velocity_x, ship_velocity_y
-            neural_net: neural::neural_net::new(vec![6 + 4, 4])
+            touchdown: None,
+            neural_net: neural::neural_net::with_initializer(
+                network_layers(design, scenario.observation.size(obstacle::RAY_COUNT)),
+                scenario.initializer,
+            ),
+            genome: None,
+            island: 0,
+            pilot: imitation::Pilot::Network,
+            recording: None,
+            trajectory: None,
+            body: None,
         }
     }
 
@@ -79,12 +327,40 @@ impl Ship {
         new_ship
     }
 
+    // Same design and input statistics, the episode state is reset when breeding
     fn clone_for_mutation(&self, lr: f32) -> Ship {
-        let mut new_ship = Ship::new();
+        let mut new_ship = self.clone();
+        new_ship.genome = None;
+        new_ship.island = 0;
         new_ship.neural_net = self.neural_net.clone_mutated(lr);
         new_ship
     }
 
+    // The network followed by its input statistics
+    fn controller_text(&self) -> String {
+        format!("{}\n{}", self.neural_net.to_text(), self.normalizer.to_text())
+    }
+
+    fn with_controller(scenario: &scenario::Scenario, (neural_net, normalizer): (neural_net, observation::Normalizer)) -> Ship {
+        let mut ship = Ship::new(scenario);
+        ship.neural_net = neural_net;
+        ship.normalizer = normalizer;
+        ship
+    }
+
+    fn same_controller(&self, other: &Ship) -> bool {
+        match (&self.genome, &other.genome) {
+            (Some(a), Some(b)) => {
+                a.connections.len() == b.connections.len()
+                    && a.connections.iter().zip(&b.connections).all(|(x, y)| {
+                        x.innovation == y.innovation && x.weight == y.weight && x.enabled == y.enabled
+                    })
+            },
+            (None, None) => self.neural_net.get_weights_flat() == other.neural_net.get_weights_flat(),
+            _ => false,
+        }
+    }
+
     fn reset(&mut self, spread: f32) {
         // Randomize the starting position
         let mut rng = rand::thread_rng();
@@ -93,17 +369,141 @@ impl Ship {
         // let xdiff: f32 = 2.;
         // let ydiff: f32 = 2.;
 
-        self.pos1 = point::Vector::new(0.5 + xdiff, 0. + ydiff);
-        self.pos2 = point::Vector::new(-0.5 + xdiff, 0. + ydiff);
-        self.pos_1_last = point::Vector::new(0.5 + xdiff, 0. + ydiff);
-        self.pos_2_last = point::Vector::new(-0.5 + xdiff, 0. + ydiff);
-        self.angle1 = 0.;
-        self.angle2 = 0.;
-        self.throttle1 = 0.;
-        self.throttle2 = 0.;
+        self.conditions = self.randomization.sample();
+        let offset = point::Vector::new(xdiff, ydiff);
+        self.points = self.design.points.iter()
+            .map(|point| point.multiplied(self.conditions.length_scale).added(&offset))
+            .collect();
+        self.points_last = self.points.clone();
+        self.motors = vec![motor::MotorState::default(); self.design.thrusters.len()];
+        self.fuel_used = 0.;
+        self.thrust_scale = 1.;
+        self.time = 0.;
+        self.sensors = sensor::SensorState::default();
+        self.output_usage = metrics::OutputUsage::default();
+        self.body = None;
         self.dead = false;
+        self.touchdown = None;
         self.score = 0.;
+        self.objectives = pareto::Objectives::default();
+        self.behaviour = novelty::Behaviour::default();
         self.best_distance = None;
+        if let Some(trajectory) = &mut self.trajectory {
+            trajectory.clear();
+        }
+    }
+
+    // One control tick: the network decides, the physics runs its substeps and the score is updated
+    fn step(&mut self, goal: &point::Vector, scenario: &scenario::Scenario) {
+        let score_before = self.score;
+        self.do_brain(goal, scenario);
+        let fuel_before = self.fuel_used;
+        for _ in 0..scenario.physics.substeps {
+            self.actuate(&scenario.motors, scenario.physics.dt);
+            self.burn_fuel(&scenario.fuel, scenario.physics.dt);
+            self.simulate(&scenario.physics, &scenario.aero);
+            if let Some(terrain) = &scenario.terrain {
+                self.check_ground(terrain, &scenario.physics);
+            }
+            self.check_obstacles(&scenario.obstacles);
+        }
+        let task_before = self.score;
+        match scenario.task {
+            scenario::Task::Tracking => self.update_score(&scenario.physics),
+            scenario::Task::Landing => self.update_landing_score(scenario),
+            scenario::Task::Navigation { .. } => self.update_navigation_score(scenario),
+        }
+        // Fuel is its own objective, so the tracking objective only sees the task score
+        self.objectives.tracking += self.score - task_before;
+        self.score += (self.fuel_used - fuel_before) * scenario.fuel.score_weight;
+        // Scores are costs
+        if let Some(trajectory) = &mut self.trajectory {
+            trajectory.reward(score_before - self.score);
+        }
+    }
+
+    // Moves the motors towards the commands of the network
+    fn actuate(&mut self, motors: &motor::MotorConfig, dt: f32) {
+        let motors = motor::MotorConfig {
+            thrust_time_constant: motors.thrust_time_constant + self.conditions.thrust_lag,
+            ..motors.clone()
+        };
+        let noise = self.conditions.actuator_noise;
+        let mut rng = rand::thread_rng();
+        for motor in &mut self.motors {
+            let mut command = motor.command.clone();
+            if noise > 0. {
+                command.throttle = (command.throttle + random::normal(&mut rng) * noise).clamp(0., 1.);
+                command.angle += random::normal(&mut rng) * noise;
+            }
+            motor.throttle = motors.actuate_throttle(motor.throttle, command.throttle, dt);
+            motor.angle = motors.actuate_angle(motor.angle, command.angle, dt);
+        }
+    }
+
+    fn total_throttle(&self) -> f32 {
+        self.motors.iter().map(|motor| motor.throttle).sum()
+    }
+
+    fn center(&self) -> point::Vector {
+        self.points.iter().fold(point::Vector::new(0., 0.), |sum, point| sum.added(point))
+            .multiplied(1. / self.points.len() as f32)
+    }
+
+    fn center_last(&self) -> point::Vector {
+        self.points_last.iter().fold(point::Vector::new(0., 0.), |sum, point| sum.added(point))
+            .multiplied(1. / self.points_last.len() as f32)
+    }
+
+    // From the second design point to the first one
+    fn axis(&self) -> point::Vector {
+        self.points[0].subtracted(&self.points[1])
+    }
+
+    fn axis_last(&self) -> point::Vector {
+        self.points_last[0].subtracted(&self.points_last[1])
+    }
+
+    // `Vector::angle` of the ship axis, this is what the network sees
+    fn heading(&self) -> f32 {
+        self.axis().angle()
+    }
+
+    // Turn rate of the heading over the last physics step in radians per second, wrapped so
+    // crossing the atan2 branch cut does not show up as a spin of 2 pi
+    fn angular_velocity(&self, dt: f32) -> f32 {
+        point::wrap_angle(self.heading() - self.axis_last().angle()) / dt
+    }
+
+    // Turn of the ship from how it sits in its design, positive when the first point went
+    // down. A design whose axis is not level is not tilted at rest.
+    fn tilt(&self) -> f32 {
+        point::wrap_angle(self.design.rest_heading() - self.heading())
+    }
+
+    fn flight_state(&self, goal: &point::Vector, dt: f32) -> imitation::FlightState {
+        imitation::FlightState {
+            to_goal: goal.subtracted(&self.center()),
+            velocity: self.center().subtracted(&self.center_last()).multiplied(1. / dt),
+            tilt: self.tilt(),
+            // The tilt grows when the heading shrinks
+            tilt_rate: -self.angular_velocity(dt),
+        }
+    }
+
+    // Burns fuel for the current throttles and cuts the motors when the tank runs dry.
+    // Thrust is scaled up as the ship gets lighter.
+    fn burn_fuel(&mut self, fuel: &fuel::FuelConfig, dt: f32) {
+        if !self.is_flying() {
+            return;
+        }
+        self.fuel_used += fuel.burned(self.total_throttle(), dt);
+        if fuel.is_empty(self.fuel_used) {
+            for motor in &mut self.motors {
+                motor.throttle = 0.;
+            }
+        }
+        self.thrust_scale = fuel.thrust_scale(self.fuel_used);
     }
 
     fn draw_motor(&self, dt: &mut DrawTarget, point: & point::Vector, angle: f32, throttle: f32) {
@@ -182,18 +582,19 @@ impl Ship {
         if self.dead {
             return;
         }
-        let camera_pos_1 = self.pos1.multiplied(100.);
-        let camera_pos_2 = self.pos2.multiplied(100.);
+        let camera_points: Vec<point::Vector> = self.points.iter().map(|point| point.multiplied(100.)).collect();
 
-        let ship_normal = self.pos1.subtracted(&self.pos2).normalized();
-        let ship_angle = ship_normal.angle() - std::f32::consts::PI / 2.;
+        let ship_angle = self.heading() - std::f32::consts::PI / 2.;
 
-        self.draw_motor(dt, &camera_pos_1, self.angle1 - ship_angle, self.throttle1);
-        self.draw_motor(dt, &camera_pos_2, self.angle2 - ship_angle, self.throttle2);
+        for (motor, thruster) in self.motors.iter().zip(&self.design.thrusters) {
+            self.draw_motor(dt, &camera_points[thruster.point], motor.angle - ship_angle, motor.throttle);
+        }
 
         let mut pb = PathBuilder::new();
-        pb.move_to(camera_pos_1.x + (WIDTH / 2) as f32, camera_pos_1.y + (HEIGHT / 2) as f32);
-        pb.line_to(camera_pos_2.x + (WIDTH / 2) as f32, camera_pos_2.y + (HEIGHT / 2) as f32);
+        for (a, b) in &self.design.links {
+            pb.move_to(camera_points[*a].x + (WIDTH / 2) as f32, camera_points[*a].y + (HEIGHT / 2) as f32);
+            pb.line_to(camera_points[*b].x + (WIDTH / 2) as f32, camera_points[*b].y + (HEIGHT / 2) as f32);
+        }
 
         let path = pb.finish();
 
@@ -212,19 +613,21 @@ impl Ship {
         )
     }
 
-    fn do_brain(&mut self, goal: &point::Vector) {
-        if self.dead {
+    fn do_brain(&mut self, goal: &point::Vector, scenario: &scenario::Scenario) {
+        if !self.is_flying() {
             return;
         }
-        // ship_angle, x_dist, y_dist, ship_angle_velocity, ship_velocity_x, ship_velocity_y
-        let ship_angle = self.pos1.subtracted(&self.pos2).normalized().angle();
-        let ship_old_angle = self.pos_1_last.subtracted(&self.pos_2_last).normalized().angle();
-        let ship_angle_velocity = ship_angle - ship_old_angle;
-
-        let ship_center = self.pos1.added(&self.pos2).multiplied(0.5);
-        let ship_center_last = self.pos_1_last.added(&self.pos_2_last).multiplied(0.5);
-        let ship_velocity_x = ship_center.x - ship_center_last.x;
-        let ship_velocity_y = ship_center.y - ship_center_last.y;
+        let physics = &scenario.physics;
+        let motors = &scenario.motors;
+        // ship_angle, x_dist, y_dist, ship_angle_velocity, ship_velocity_x, ship_velocity_y, fuel
+        let ship_angle = self.heading();
+        // Velocities are per second, the last positions are from the previous physics step
+        let ship_angle_velocity = self.angular_velocity(physics.dt);
+
+        let ship_center = self.center();
+        let ship_center_last = self.center_last();
+        let ship_velocity_x = (ship_center.x - ship_center_last.x) / physics.dt;
+        let ship_velocity_y = (ship_center.y - ship_center_last.y) / physics.dt;
 
         let vector_to_goal = goal.subtracted(&ship_center);
 
@@ -233,38 +636,82 @@ impl Ship {
 
         let last_layer = self.neural_net.get_last_layer();
 
-        self.neural_net.set_first_layer(vec![
+        // Raw observation, laid out as `observation::RAW_STATE_SIZE` says
+        let mut observation = vec![
             ship_angle,
             x_dist,
             y_dist,
             ship_angle_velocity,
             ship_velocity_x,
             ship_velocity_y,
-            // Last layer that is used
-            0., //last_layer[0],
-            0., //last_layer[1],
-            0., //last_layer[2],
-            0., //last_layer[3],
-            // Extras from last layer
-            // last_layer[4],
-            // last_layer[5],
-            // last_layer[6],
-        ]);
-        self.neural_net.forward_propagate();
-        let output = self.neural_net.get_last_layer();
-
-        self.throttle1 = output[0] * 1.0;
-        self.throttle2 = output[1] * 1.0;
-        self.angle1 = (output[2] - 0.5) * 2.0;
-        self.angle2 = (output[3] - 0.5) * 2.0;
+            scenario.fuel.remaining_fraction(self.fuel_used),
+        ];
+        observation.extend(self.range_readings(scenario));
+        let measured = scenario.sensors.observe(&mut self.sensors, observation, physics.control_dt());
+        let mut inputs = scenario.observation.encode(&measured, &mut self.normalizer);
+        // Last layer that is used, one slot per network output
+        // inputs.extend(last_layer.iter());
+        inputs.extend(vec![0.; self.design.output_size()]);
+        let piloted = self.pilot.outputs(&self.flight_state(goal, physics.dt), &self.design, motors, physics);
+        let output = match (piloted, &self.genome) {
+            (Some(output), _) => output,
+            (None, Some(genome)) => genome.activate(&inputs),
+            (None, None) => {
+                self.neural_net.set_first_layer(inputs.clone());
+                self.neural_net.forward_propagate();
+                self.neural_net.get_last_layer()
+            }
+        };
+        self.output_usage.record(&output);
+        let output = match &mut self.trajectory {
+            Some(trajectory) => trajectory.explore(inputs.clone(), output),
+            None => output,
+        };
+        if let Some(recording) = &mut self.recording {
+            recording.push(inputs, output.clone());
+        }
+
+        // All throttles first, then all gimbal angles
+        let thruster_count = self.motors.len();
+        for (i, (motor, thruster)) in self.motors.iter_mut().zip(&self.design.thrusters).enumerate() {
+            let command = motor::MotorCommand {
+                throttle: motors.throttle_command(output[i]),
+                angle: motors.angle_command(output[thruster_count + i], thruster.gimbal_range),
+            };
+            self.objectives.smoothness += (command.throttle - motor.command.throttle).abs()
+                + (command.angle - motor.command.angle).abs();
+            motor.command = command;
+        }
         // self.angle1 = 0.0;
         // self.angle2 = 0.0;
         // Print debug angles and throttles
         // println!("Angle1: {}, Angle2: {}, Throttle1: {}, Throttle2: {}", self.angle1, self.angle2, self.throttle1, self.throttle2);
     }
 
-    fn update_score(&mut self) {
-        let middle = self.pos1.added(&self.pos2).multiplied(0.5);
+    // Distance to the nearest obstacle or ground along each sensor ray, as a fraction of the
+    // sensor range. 1 means nothing in range.
+    fn range_readings(&self, scenario: &scenario::Scenario) -> [f32; obstacle::RAY_COUNT] {
+        let mut readings = [1.; obstacle::RAY_COUNT];
+        let segments = match &scenario.terrain {
+            Some(terrain) => terrain.segments(),
+            None => vec![],
+        };
+        if scenario.obstacles.is_empty() && segments.is_empty() {
+            return readings;
+        }
+        let center = self.center();
+        let ship_angle = self.heading();
+        for (i, reading) in readings.iter_mut().enumerate() {
+            let angle = ship_angle + i as f32 / obstacle::RAY_COUNT as f32 * 2. * std::f32::consts::PI;
+            let direction = point::Vector::new(angle.cos(), angle.sin());
+            let distance = obstacle::cast_ray(&scenario.obstacles, &segments, &center, &direction, scenario.sensor_range);
+            *reading = distance / scenario.sensor_range;
+        }
+        readings
+    }
+
+    fn update_score(&mut self, physics: &physics::PhysicsConfig) {
+        let middle = self.center();
         let x_dist = middle.x;
         let y_dist = middle.y;
         let distance = (x_dist * x_dist + y_dist * y_dist).sqrt();
@@ -279,13 +726,16 @@ impl Ship {
                     self.best_distance = Some(distance);
                     self.score -= best - distance;
                 } else {
-                    self.score += distance - best;
+                    // Being further than the best distance costs for as long as it lasts
+                    self.score += (distance - best) * physics.control_dt();
                 }
             }
         }
 
-        let speed_x = self.pos1.x - self.pos_1_last.x;
-        let speed_y = self.pos1.y - self.pos_1_last.y;
+        self.record_objectives(physics, distance);
+
+        let speed_x = self.points[0].x - self.points_last[0].x;
+        let speed_y = self.points[0].y - self.points_last[0].y;
         let speed = (speed_x * speed_x + speed_y * speed_y).sqrt();
         let speed_pow = ((speed + 1.0) * 10.).powf(2.);
         let speed_score = 0.;
@@ -293,83 +743,376 @@ impl Ship {
         // self.score += distance_score + speed_score;
     }
 
-    fn simulate(&mut self) {
-        if self.dead {
+    // Landing score per second: distance to the target pad while flying, nothing once landed
+    // on it, and a fixed cost plus how hard and tilted the impact was for every second after a crash
+    fn update_landing_score(&mut self, scenario: &scenario::Scenario) {
+        let (terrain, target) = match (&scenario.terrain, scenario.fixed_goal()) {
+            (Some(terrain), Some(target)) => (terrain, target),
+            _ => return,
+        };
+        let middle = self.center();
+        let distance = middle.subtracted(&target).length();
+        let cost = match &self.touchdown {
+            None if self.dead => CRASH_COST,
+            None => distance,
+            Some(touchdown) if touchdown.landed_on(terrain.target_pad) => 0.,
+            Some(touchdown) if touchdown.kind == terrain::TouchdownKind::Landed => (middle.x - target.x).abs(),
+            Some(touchdown) => CRASH_COST + touchdown.speed + touchdown.tilt.abs(),
+        };
+        self.score += cost * scenario.physics.control_dt();
+        self.record_objectives(&scenario.physics, distance);
+    }
+
+    // Navigation score per second: distance to the goal, or the crash cost once dead
+    fn update_navigation_score(&mut self, scenario: &scenario::Scenario) {
+        let target = match scenario.fixed_goal() {
+            Some(target) => target,
+            None => return,
+        };
+        let middle = self.center();
+        let distance = middle.subtracted(&target).length();
+        self.score += if self.dead { CRASH_COST } else { distance } * scenario.physics.control_dt();
+        self.record_objectives(&scenario.physics, distance);
+    }
+
+    fn record_objectives(&mut self, physics: &physics::PhysicsConfig, distance: f32) {
+        let middle = self.center();
+        self.objectives.fuel += self.total_throttle() * physics.control_dt();
+        self.objectives.robustness = self.objectives.robustness.max(distance);
+
+        self.behaviour.record(middle.x, middle.y, self.tilt(), self.total_throttle() / self.motors.len() as f32);
+    }
+
+    fn simulate_point_masses(&mut self, physics: &physics::PhysicsConfig, aero: &wind::AeroConfig, thrust: &[point::Vector]) {
+        let integrator = physics.integrator.integrator();
+        let gravity = point::Vector::new(0., physics.gravity * self.conditions.gravity_scale);
+        let time = self.time;
+        let mass_scale = self.conditions.mass_scale;
+
+        for (i, point_thrust) in thrust.iter().enumerate() {
+            let acceleration = gravity.added(point_thrust);
+            // Velocities are implied by the last positions
+            let state = integrator::PointState {
+                velocity: self.points[i].subtracted(&self.points_last[i]).multiplied(1. / physics.dt),
+                position: self.points[i].clone(),
+            };
+            let state = integrator.step(
+                &state,
+                &|position, velocity| acceleration.added(
+                    &aero.drag_acceleration(position, velocity, time).multiplied(1. / mass_scale)
+                ),
+                physics.dt,
+            );
+            // Last positions carry the new velocities. The constraints below only move the
+            // current positions, so their correction ends up in the velocity as well.
+            self.points[i] = state.position;
+            self.points_last[i] = self.points[i].subtracted(&state.velocity.multiplied(physics.dt));
+        }
+
+        // Make sure the linked points stay at their rest distance
+        for _ in 0..physics.constraint_iterations {
+            for link in &self.design.links {
+                let rest_length = self.design.rest_length(link) * self.conditions.length_scale;
+                let (a, b) = *link;
+                let direction = self.points[b].added(&self.points[a].negated());
+                let distance = direction.length();
+                let correction = direction.multiplied((distance - rest_length) / distance * 0.5);
+                self.points[a].add(&correction);
+                self.points[b].add(&correction.negated());
+            }
+        }
+    }
+
+    fn simulate_rigid_body(&mut self, physics: &physics::PhysicsConfig, aero: &wind::AeroConfig, thrust: &[point::Vector]) {
+        let conditions = &self.conditions;
+        let (mass, inertia) = match physics.body_model {
+            physics::BodyModel::RigidBody { mass, inertia } => (
+                mass * conditions.mass_scale,
+                inertia * conditions.mass_scale * conditions.length_scale.powi(2),
+            ),
+            physics::BodyModel::PointMasses => return,
+        };
+        let mut body = self.body.take().unwrap_or_else(|| rigid_body::RigidBody::from_points(
+            &self.points, &self.points_last, physics.dt, mass, inertia
+        ));
+
+        // Thrust and drag accelerations are per point, each point carries an equal share of the
+        // mass. Thrust already carries the mass scale, drag is for the unscaled mass like in
+        // `simulate_point_masses`.
+        let point_mass = mass / self.points.len() as f32;
+        let mounts: Vec<point::Vector> = self.design.local_points().iter()
+            .map(|mount| mount.multiplied(conditions.length_scale))
+            .collect();
+        let forces: Vec<(point::Vector, point::Vector)> = mounts.iter().enumerate().map(|(i, mount)| {
+            let offset = body.rotated(mount);
+            let drag = aero.drag_acceleration(&self.points[i], &body.point_velocity(&offset), self.time);
+            (thrust[i].added(&drag.multiplied(1. / conditions.mass_scale)).multiplied(point_mass), offset)
+        }).collect();
+        body.step(&forces, &point::Vector::new(0., physics.gravity * conditions.gravity_scale), physics.dt);
+
+        self.points_last = self.points.clone();
+        self.points = mounts.iter().map(|mount| body.world_point(mount)).collect();
+        self.body = Some(body);
+    }
+
+    fn is_flying(&self) -> bool {
+        !self.dead && self.touchdown.is_none()
+    }
+
+    // World direction a motor pushes in, its nozzle points the other way
+    fn motor_direction(&self, motor_angle: f32) -> point::Vector {
+        // At a tilt and gimbal angle of 0 the motors push straight up
+        let real_angle = motor_angle + self.tilt();
+        point::Vector::new(real_angle.sin(), -real_angle.cos())
+    }
+
+    // Every point and every motor nozzle, motors are drawn 0.2 long on each side of their point
+    fn collision_points(&self) -> Vec<point::Vector> {
+        let nozzles = self.motors.iter().zip(&self.design.thrusters).map(|(motor, thruster)| {
+            self.points[thruster.point].subtracted(&self.motor_direction(motor.angle).multiplied(0.2))
+        });
+        self.points.iter().cloned().chain(nozzles).collect()
+    }
+
+    // Ends the flight when any collision point is in the ground. A soft and level touchdown
+    // leaves the ship resting on the ground, anything else is a crash.
+    fn check_ground(&mut self, terrain: &terrain::Terrain, physics: &physics::PhysicsConfig) {
+        if !self.is_flying() {
+            return;
+        }
+        let penetration = self.collision_points().iter()
+            .map(|point| terrain.penetration(point))
+            .fold(0., f32::max);
+        if penetration <= 0. {
             return;
         }
-        // Store current position for storing later
-        let temp1 = self.pos1.clone();
-        let temp2 = self.pos2.clone();
-
-        let ship_normal = self.pos1.subtracted(&self.pos2).normalized();
-        let ship_angle = ship_normal.angle() - std::f32::consts::PI / 2.;
-
-        let real_angle_1 = self.angle1 - ship_angle;
-        let real_angle_2 = self.angle2 - ship_angle;
-
-        // Do verlet stuff
-        self.pos1.add(
-            // Current position
-            &self.pos1
-            // Minus old position
-            .added(&self.pos_1_last.multiplied(-1.))
-            // Add gravity
-            .added(&point::Vector::new(0., 0.002))
-            // Add throttle
-            .added(
-                &point::Vector::new(
-                    real_angle_1.sin(),
-                    -real_angle_1.cos()
-                ).multiplied(self.throttle1 * 0.005)
-            )
-        );
 
-        self.pos2.add(
-            // Current position
-            &self.pos2
-            // Minus old position
-            .added(&self.pos_2_last.multiplied(-1.))
-            // Add gravity
-            .added(&point::Vector::new(0., 0.002))
-            // Add throttle
-            .added(
-                &point::Vector::new(
-                    real_angle_2.sin(),
-                    -real_angle_2.cos()
-                ).multiplied(self.throttle2 * 0.005)
-            )
-        );
+        let center = self.center();
+        let speed = center.subtracted(&self.center_last()).length() / physics.dt;
+        let touchdown = terrain.classify(center.x, speed, self.tilt());
 
-        // Make sure distance between points stays 1
-        let direction = self.pos2.added(&self.pos1.negated());
-        let distance = direction.length();
-        self.pos1.add(
-            &direction.multiplied(
-                (distance - 1.0) / distance * 0.5
-            )
-        );
-        self.pos2.add(
-            &direction.multiplied(
-                (distance - 1.0) / distance * 0.5
-            ).negated()
-        );
+        if touchdown.kind == terrain::TouchdownKind::Crashed {
+            self.dead = true;
+        }
+        // Rest on the surface with the motors off
+        let lift = point::Vector::new(0., -penetration);
+        for point in &mut self.points {
+            point.add(&lift);
+        }
+        self.points_last = self.points.clone();
+        for motor in &mut self.motors {
+            motor.throttle = 0.;
+        }
+        self.body = None;
+        self.touchdown = Some(touchdown);
+    }
+
+    // The ship body is made of the link segments, touching an obstacle with one is a crash
+    fn check_obstacles(&mut self, obstacles: &[obstacle::Obstacle]) {
+        if !self.is_flying() {
+            return;
+        }
+        let touches = |(a, b): &(usize, usize)| {
+            obstacles.iter().any(|obstacle| obstacle.intersects_segment(&self.points[*a], &self.points[*b]))
+        };
+        if self.design.links.iter().any(touches) {
+            self.dead = true;
+        }
+    }
+
+    // Advances the physics by one step of physics.dt
+    fn simulate(&mut self, physics: &physics::PhysicsConfig, aero: &wind::AeroConfig) {
+        if !self.is_flying() {
+            return;
+        }
+        // Acceleration the motors give each point
+        let mut thrust = vec![point::Vector::new(0., 0.); self.points.len()];
+        for (motor, thruster) in self.motors.iter().zip(&self.design.thrusters) {
+            thrust[thruster.point].add(&self.motor_direction(motor.angle).multiplied(
+                motor.throttle * thruster.max_thrust * physics.thrust_acceleration * self.thrust_scale
+                    * self.conditions.thrust_scale / self.conditions.mass_scale
+            ));
+        }
 
-        // Save old position from temp
-        self.pos_1_last.x = temp1.x;
-        self.pos_1_last.y = temp1.y;
-        self.pos_2_last.x = temp2.x;
-        self.pos_2_last.y = temp2.y;
+        match physics.body_model {
+            physics::BodyModel::PointMasses => self.simulate_point_masses(physics, aero, &thrust),
+            physics::BodyModel::RigidBody { .. } => self.simulate_rigid_body(physics, aero, &thrust),
+        }
+        self.time += physics.dt;
 
         // Set to dead if out of bounds
-        if self.pos1.length() > 10.0 {
+        if self.points.iter().any(|point| point.length() > 10.0) {
             self.dead = true;
         }
     }
 }
 
+// Observations plus one slot per output in, throttle and gimbal angle per thruster out
+fn network_layers(design: &ship_design::ShipDesign, observation_size: usize) -> Vec<u32> {
+    let outputs = design.output_size();
+    vec![(observation_size + outputs) as u32, outputs as u32]
+}
+
+// Reads back `Ship::controller_text`. Networks saved without statistics get empty ones.
+fn controller_from_text(text: &str) -> Option<(neural_net, observation::Normalizer)> {
+    let net = neural_net::from_text(text)?;
+    Some((net, observation::Normalizer::from_text(text).unwrap_or_default()))
+}
+
+fn objective_points(ships: &[Ship]) -> Vec<[f32; pareto::OBJECTIVE_COUNT]> {
+    ships.iter().map(|ship| ship.objectives.as_array()).collect()
+}
+
 fn do_ship_mutation(ships: &mut Vec<Ship>, spread: f32, lr: f32) {
-    ships.sort_by(|a, b| a.score.partial_cmp(&b.score).unwrap());
+    // NSGA-II selection: order by Pareto front, then by crowding distance within the front
+    let order = pareto::nsga2_order(&objective_points(ships));
+    *ships = order.iter().map(|&i| ships[i].clone()).collect();
+
+    breed_ordered_ships(ships, spread, lr);
+}
+
+fn do_novelty_mutation(ships: &mut Vec<Ship>, archive: &mut novelty::NoveltyArchive, spread: f32, lr: f32) {
+    let behaviours: Vec<[f32; novelty::DESCRIPTOR_SIZE]> = ships.iter().map(|ship| ship.behaviour.descriptor()).collect();
+    let novelty_scores = archive.novelty_scores(&behaviours);
+    archive.add_from(&behaviours);
+
+    // Most novel first
+    let mut order: Vec<usize> = (0..ships.len()).collect();
+    order.sort_by(|a, b| novelty_scores[*b].partial_cmp(&novelty_scores[*a]).unwrap());
+    println!("Novelty: best {} archive size {}", novelty_scores[order[0]], archive.len());
+    *ships = order.iter().map(|&i| ships[i].clone()).collect();
+
+    breed_ordered_ships(ships, spread, lr);
+}
+
+fn do_map_elites_mutation(ships: &mut [Ship], grid: &mut map_elites::MapElites, episode_seconds: f32, spread: f32, lr: f32) {
+    // Episodes grow longer every generation, scores per second keep early elites comparable
+    for ship in ships.iter() {
+        grid.insert(&ship.neural_net, &ship.normalizer, ship.score / episode_seconds, ship.behaviour.descriptor());
+    }
+    let elites = grid.elites();
+    println!("Map elites: {} cells filled", grid.filled_cells());
+
+    // Every new ship is a mutated elite, half of them crossed with another elite
+    let mut rng = rand::thread_rng();
+    for ship in ships.iter_mut() {
+        let first = elites[rng.gen_range(0..elites.len())];
+        let mut new_net = first.neural_net.clone_mutated(lr);
+        if rng.gen::<f32>() < 0.5 {
+            let second = elites[rng.gen_range(0..elites.len())];
+            new_net = new_net.mix_randomly_with_other(&second.neural_net.clone_mutated(lr));
+        }
+        ship.neural_net = new_net;
+        ship.normalizer = first.normalizer.clone();
+        ship.reset(spread);
+    }
+}
+
+fn do_neat_mutation(ships: &mut [Ship], population: &mut neat::NeatPopulation, spread: f32) {
+    let scored: Vec<(neat::Genome, f32)> = ships.iter()
+        .filter_map(|ship| ship.genome.clone().map(|genome| (genome, ship.score)))
+        .collect();
+    let best = scored.iter().min_by(|a, b| a.1.partial_cmp(&b.1).unwrap()).map(|(genome, _)| genome.clone());
+
+    let genomes = population.next_generation(scored);
+    if let Some(best) = best {
+        println!(
+            "Neat: {} species, best has {} hidden nodes and {} connections",
+            population.species_count(),
+            best.hidden_node_count(),
+            best.enabled_connection_count()
+        );
+    }
+
+    for (ship, genome) in ships.iter_mut().zip(genomes) {
+        ship.genome = Some(genome);
+        ship.reset(spread);
+    }
+}
+
+// Evolution strategies: the ships carry the samples, so their weights and scores are all that is needed
+fn set_ship_samples(ships: &mut [Ship], samples: Vec<Vec<f32>>, spread: f32) {
+    for (ship, sample) in ships.iter_mut().zip(samples) {
+        ship.neural_net.set_weights_flat(&sample);
+        ship.reset(spread);
+    }
+}
+
+fn do_cmaes_mutation(ships: &mut [Ship], strategy: &mut cmaes::CmaEs, spread: f32) {
+    let samples: Vec<Vec<f32>> = ships.iter().map(|ship| ship.neural_net.get_weights_flat()).collect();
+    let scores: Vec<f32> = ships.iter().map(|ship| ship.score).collect();
+    strategy.tell(&samples, &scores);
+    println!("Cmaes: sigma {}", strategy.sigma);
+    set_ship_samples(ships, strategy.ask(), spread);
+}
+
+// Every ship gets the current policy and a fresh rollout
+fn start_rollouts(ships: &mut [Ship], trainer: &policy_gradient::PolicyGradient, spread: f32) {
+    for ship in ships {
+        ship.neural_net = trainer.policy.clone();
+        ship.normalizer = trainer.normalizer.clone();
+        ship.trajectory = Some(policy_gradient::Trajectory::new(trainer.std()));
+        ship.reset(spread);
+    }
+}
+
+fn do_policy_gradient_update(ships: &mut [Ship], trainer: &mut policy_gradient::PolicyGradient, spread: f32) {
+    let trajectories: Vec<policy_gradient::Trajectory> = ships.iter_mut().filter_map(|ship| ship.trajectory.take()).collect();
+    trainer.update(&trajectories);
+    // Every ship added its own inputs to a copy of the shared statistics
+    let start = trainer.normalizer.clone();
+    for ship in ships.iter() {
+        trainer.normalizer.merge(&ship.normalizer.added_since(&start));
+    }
+    start_rollouts(ships, trainer, spread);
+}
+
+fn do_nes_mutation(ships: &mut [Ship], strategy: &mut nes::Nes, spread: f32) {
+    let samples: Vec<Vec<f32>> = ships.iter().map(|ship| ship.neural_net.get_weights_flat()).collect();
+    let scores: Vec<f32> = ships.iter().map(|ship| ship.score).collect();
+    strategy.tell(&samples, &scores);
+    set_ship_samples(ships, strategy.ask(), spread);
+}
+
+fn do_island_mutation(ships: &mut Vec<Ship>, model: &mut island::IslandModel, spread: f32) {
+    let mut islands: Vec<Vec<Ship>> = vec![vec![]; model.island_count()];
+    for ship in ships.drain(..) {
+        let index = ship.island.min(islands.len() - 1);
+        islands[index].push(ship);
+    }
+    for island in &mut islands {
+        island.sort_by(|a, b| a.score.partial_cmp(&b.score).unwrap());
+    }
+
+    model.generation += 1;
+    if model.is_migration_generation() {
+        island::migrate(&mut islands, model.migrant_count);
+    }
+
+    let best_per_island: Vec<String> = islands.iter()
+        .map(|island| island.first().map(|ship| ship.score.to_string()).unwrap_or_default())
+        .collect();
+    println!("Islands: [{}]", best_per_island.join(" "));
+
+    let learning_rates = model.learning_rates.clone();
+    islands.par_iter_mut().enumerate().for_each(|(index, island)| {
+        if island.is_empty() {
+            return;
+        }
+        do_ship_mutation(island, spread, learning_rates[index]);
+        for ship in island.iter_mut() {
+            ship.island = index;
+        }
+    });
 
+    for island in islands {
+        ships.extend(island);
+    }
+}
+
+// Expects ships to be ordered best first. Keeps the best half and refills the rest
+// with mutated (and crossed) copies, biased towards the front of the list.
+fn breed_ordered_ships(ships: &mut Vec<Ship>, spread: f32, lr: f32) {
     let mut new_ships:Vec<Ship> = vec![];
 
     for i in 0..(ships.len() / 2) {
@@ -398,7 +1141,20 @@ fn do_ship_mutation(ships: &mut Vec<Ship>, spread: f32, lr: f32) {
     }
 }
 
-fn iterate_draw(ships: &mut Vec<Ship>, steps: i32, spread: f32, lr: f32) {
+fn next_generation(ships: &mut Vec<Ship>, mode: &mut TrainingMode, episode_seconds: f32, spread: f32, lr: f32) {
+    match mode {
+        TrainingMode::Genetic => do_ship_mutation(ships, spread, lr),
+        TrainingMode::Novelty(archive) => do_novelty_mutation(ships, archive, spread, lr),
+        TrainingMode::MapElites(grid) => do_map_elites_mutation(ships, grid, episode_seconds, spread, lr),
+        TrainingMode::Neat(population) => do_neat_mutation(ships, population, spread),
+        TrainingMode::CmaEs(strategy) => do_cmaes_mutation(ships, strategy, spread),
+        TrainingMode::Nes(strategy) => do_nes_mutation(ships, strategy, spread),
+        TrainingMode::Islands(model) => do_island_mutation(ships, model, spread),
+        TrainingMode::PolicyGradient(trainer) => do_policy_gradient_update(ships, trainer, spread),
+    }
+}
+
+fn iterate_draw(ships: &mut Vec<Ship>, scenario: &scenario::Scenario, steps: i32, spread: f32, lr: f32) {
     let mut window = Window::new(
         "Raqote",
         WIDTH,
@@ -415,7 +1171,8 @@ fn iterate_draw(ships: &mut Vec<Ship>, steps: i32, spread: f32, lr: f32) {
     let size = window.get_size();
     let mut dt = DrawTarget::new(size.0 as i32, size.1 as i32);
 
-    window.limit_update_rate(Some(std::time::Duration::from_micros(16600)));
+    // One control tick per frame keeps the viewer running in real time
+    window.limit_update_rate(Some(std::time::Duration::from_secs_f32(scenario.physics.control_dt())));
 
     let mut iteration = 0;
     // Max value of f32
@@ -438,11 +1195,19 @@ fn iterate_draw(ships: &mut Vec<Ship>, steps: i32, spread: f32, lr: f32) {
         }
 
 
+        // All ships are reset together, so any of them holds the episode time
+        if let Some(ship) = ships.first() {
+            draw_wind(&mut dt, &scenario.aero.wind, ship.time);
+        }
+        if let Some(terrain) = &scenario.terrain {
+            draw_terrain(&mut dt, terrain);
+        }
+        draw_obstacles(&mut dt, &scenario.obstacles);
+        let goal = scenario.fixed_goal().unwrap_or(mouse_pos_world.clone());
+
         // Iterate each ship
         for ship in &mut *ships {
-            ship.do_brain(&mouse_pos_world);
-            ship.simulate();
-            ship.update_score();
+            ship.step(&goal, scenario);
             ship.draw(&mut dt);
         }
 
@@ -469,7 +1234,21 @@ fn iterate_draw(ships: &mut Vec<Ship>, steps: i32, spread: f32, lr: f32) {
     }
 }
 
-fn iterate_raw(ships: &mut Vec<Ship>, steps: i32, spread: f32, lr: f32, step_n: i32, iteration_n: i32) -> f32 {
+// Goal of a training episode at the given control tick
+fn episode_goal(scenario: &scenario::Scenario, step_n: usize, steps: i32, spread: f32, iteration_n: i32) -> point::Vector {
+    // Landing and navigating ships always aim for the same point
+    if let Some(target) = scenario.fixed_goal() {
+        return target;
+    }
+    // Goal is a unit circle
+    let direction = if iteration_n % 2 == 0 { 1. } else { -1. };
+    point::Vector::new(
+        (direction * step_n as f32 / steps as f32 * 2. * std::f32::consts::PI * 10.).sin() * spread,
+        (direction * step_n as f32 / steps as f32 * 2. * std::f32::consts::PI * 10.).cos() * spread,
+    )
+}
+
+fn simulate_ships(ships: &mut Vec<Ship>, scenario: &scenario::Scenario, steps: i32, spread: f32, iteration_n: i32) {
     let THREAD_COUNT: usize = 16;
     let vec: Vec<i64> = (0..(THREAD_COUNT as i64)).collect();
 
@@ -494,22 +1273,13 @@ fn iterate_raw(ships: &mut Vec<Ship>, steps: i32, spread: f32, lr: f32, step_n:
         }
 
         // Random goal based on spread
-        let mut goal = point::Vector::new(0., 0.);
         // goal.x = rng.gen::<f32>() * spread - spread / 2.;
         // goal.y = rng.gen::<f32>() * spread - spread / 2.;
 
         for step_n in 0..(steps as usize) {
-            // Goal is a unit circle
-            let direction = if iteration_n % 2 == 0 { 1. } else { -1. };
-            goal = point::Vector::new(
-                (direction * step_n as f32 / steps as f32 * 2. * std::f32::consts::PI * 10.).sin() * spread,
-                (direction * step_n as f32 / steps as f32 * 2. * std::f32::consts::PI * 10.).cos() * spread,
-            );
-
+            let goal = episode_goal(scenario, step_n, steps, spread, iteration_n);
             for ship in &mut splitted_ships {
-                ship.do_brain(&goal);
-                ship.simulate();
-                ship.update_score();
+                ship.step(&goal, scenario);
             }
         }
         splitted_ships
@@ -522,51 +1292,481 @@ fn iterate_raw(ships: &mut Vec<Ship>, steps: i32, spread: f32, lr: f32, step_n:
             ships.push(ship);
         }
     }
+}
 
-    // Get average score of ships
-    let mut average_score = 0.;
-    for ship in &mut * ships {
-        average_score += ship.score;
-    }
-    average_score /= ships.len() as f32;
+fn iterate_raw(ships: &mut Vec<Ship>, mode: &TrainingMode, scenario: &scenario::Scenario, steps: i32, spread: f32, lr: f32, step_n: i32) -> f32 {
+    simulate_ships(ships, scenario, steps, spread, step_n);
 
     ships.sort_by(|a, b| a.score.partial_cmp(&b.score).unwrap());
-    let best_scores = ships.iter().map(|ship| ship.score).take(8).collect::<Vec<f32>>();
-    let best_score_string = best_scores.iter().map(|score| score.to_string()).collect::<Vec<String>>().join(" ");
-    // println!("Average score: {} [{}]", average_score, best_score_string);
+    let scores: Vec<f32> = ships.iter().map(|ship| ship.score).collect();
+    let average_score = metrics::log_generation(mode.name(), step_n, lr, &scores, spread);
+
+    if scenario.randomization.is_enabled() {
+        let episodes: Vec<(f32, bool, &randomization::EpisodeConditions)> = ships.iter()
+            .map(|ship| (ship.score, ship.dead, &ship.conditions))
+            .collect();
+        metrics::log_episodes(step_n, &episodes);
+    }
 
-    // Get scores of top 10% of ships
-    let best_scores_of_10_percent = ships.iter().map(|ship| ship.score).take((ships.len() as f32 * 0.1) as usize).collect::<Vec<f32>>();
-    let average_of_top_10_percent = best_scores_of_10_percent.iter().sum::<f32>() / best_scores_of_10_percent.len() as f32;
+    if scenario.terrain.is_some() {
+        let touchdowns: Vec<&terrain::Touchdown> = ships.iter().filter_map(|ship| ship.touchdown.as_ref()).collect();
+        let crashed = touchdowns.iter().filter(|touchdown| touchdown.kind == terrain::TouchdownKind::Crashed).count();
+        let on_pad = touchdowns.iter().filter(|touchdown| touchdown.kind == terrain::TouchdownKind::Landed && touchdown.pad.is_some()).count();
+        println!(
+            "Touchdowns: {} landed on a pad, {} landed elsewhere, {} crashed, {} still flying",
+            on_pad,
+            touchdowns.len() - crashed - on_pad,
+            crashed,
+            ships.iter().filter(|ship| ship.is_flying()).count(),
+        );
+    }
 
-    println!("Average score: {} {} {} {} [{}] {}", step_n, lr, average_of_top_10_percent, average_score, best_score_string, spread);
+    metrics::log_diagnostics(step_n, &population_diagnostics(ships));
 
-    do_ship_mutation(ships, 0., lr);
+    if let Err(e) = pareto::export_front("pareto_front.csv", step_n, &objective_points(ships)) {
+        println!("Could not export pareto front: {}", e);
+    }
 
     average_score
 }
 
+// Ships the diversity is measured on, the pairs grow with the square of it
+const DIVERSITY_SAMPLE: usize = 100;
+
+// Average distance between the weights of a sample of ships. None for neat genomes,
+// which do not share a weight layout.
+fn population_diversity(ships: &[Ship]) -> Option<f32> {
+    if ships.iter().any(|ship| ship.genome.is_some()) || ships.len() < 2 {
+        return None;
+    }
+    // Ships are sorted by score, a uniform sample stands for the whole population
+    let mut rng = rand::thread_rng();
+    let sample: Vec<Vec<f32>> = rand::seq::index::sample(&mut rng, ships.len(), ships.len().min(DIVERSITY_SAMPLE))
+        .iter()
+        .map(|index| ships[index].neural_net.get_weights_flat())
+        .collect();
+    let mut total = 0.;
+    let mut pairs = 0;
+    for i in 0..sample.len() {
+        for j in (i + 1)..sample.len() {
+            total += sample[i].iter().zip(&sample[j]).map(|(a, b)| (a - b).powi(2)).sum::<f32>().sqrt();
+            pairs += 1;
+        }
+    }
+    Some(total / pairs as f32)
+}
+
+// Bit patterns of the controller parameters, equal only for identical controllers
+fn controller_key(ship: &Ship) -> Vec<u32> {
+    match &ship.genome {
+        Some(genome) => genome.connections.iter()
+            .flat_map(|connection| [connection.innovation as u32, connection.weight.to_bits(), connection.enabled as u32])
+            .collect(),
+        None => ship.neural_net.get_weights_flat().iter().map(|weight| weight.to_bits()).collect(),
+    }
+}
+
+fn population_diagnostics(ships: &[Ship]) -> metrics::Diagnostics {
+    let mut output_usage = metrics::OutputUsage::default();
+    for ship in ships {
+        output_usage.add(&ship.output_usage);
+    }
+    let distinct: std::collections::HashSet<Vec<u32>> = ships.iter().map(controller_key).collect();
+
+    let mut layers = vec![];
+    if ships.iter().all(|ship| ship.genome.is_none()) && !ships.is_empty() {
+        for layer in 0..ships[0].neural_net.weight_layers().len() {
+            let weights: Vec<f32> = ships.iter().flat_map(|ship| ship.neural_net.weight_layers()[layer].iter().copied()).collect();
+            let mean = weights.iter().sum::<f32>() / weights.len() as f32;
+            let variance = weights.iter().map(|weight| (weight - mean).powi(2)).sum::<f32>() / weights.len() as f32;
+            layers.push((mean, variance.sqrt()));
+        }
+    }
+
+    metrics::Diagnostics {
+        layers,
+        sampled_pairwise_distance: population_diversity(ships),
+        distinct_controllers: distinct.len(),
+        population: ships.len(),
+        output_usage,
+    }
+}
+
+// Episode length the stopping criteria are measured on, the training episodes grow longer
+// every generation and their scores are not comparable
+const PROGRESS_EPISODE_SECONDS: f32 = 200. / 60.;
+
+// Score per second of the ship on an episode of fixed length, started in the middle
+fn fixed_length_score(ship: &Ship, scenario: &scenario::Scenario, spread: f32) -> f32 {
+    let steps = scenario.physics.control_ticks(PROGRESS_EPISODE_SECONDS);
+    let mut probe = vec![ship.clone()];
+    probe[0].trajectory = None;
+    probe[0].reset(0.);
+    simulate_ships(&mut probe, scenario, steps, spread, 0);
+    probe[0].score / scenario.physics.episode_seconds(steps)
+}
+
+fn update_hall_of_fame(hall_of_fame: &mut hall_of_fame::HallOfFame<Ship>, ships: &[Ship], scenario: &scenario::Scenario, steps: i32, spread: f32, step_n: i32) {
+    // Scores grow with the episode length, so they are stored per second
+    let episode_seconds = scenario.physics.episode_seconds(steps);
+    for ship in ships.iter().take(hall_of_fame.capacity) {
+        // Members fly without exploration noise, the policy gradient trajectory would only take up memory
+        let mut member = ship.clone();
+        member.trajectory = None;
+        hall_of_fame.consider(&member, ship.score / episode_seconds, step_n, Ship::same_controller);
+    }
+
+    if hall_of_fame.should_reevaluate(step_n) {
+        let mut members = hall_of_fame.champions(hall_of_fame.capacity);
+        for member in &mut members {
+            // Champions are judged without exploration noise
+            member.reset(0.);
+        }
+        simulate_ships(&mut members, scenario, steps, spread, step_n);
+        // Simulation shuffles the ships, so scores are matched back by controller
+        let scores: Vec<f32> = hall_of_fame.entries.iter().map(|entry| {
+            members.iter()
+                .find(|member| entry.member.same_controller(member))
+                .map(|member| member.score / episode_seconds)
+                .unwrap_or(entry.score)
+        }).collect();
+        hall_of_fame.add_reevaluation_scores(&scores);
+    }
+
+    if let Some(best) = hall_of_fame.entries.first() {
+        println!("Hall of fame: best {} from generation {} ({} evaluations)", best.score, best.generation, best.evaluations);
+    }
+}
+
+// Replaces the last ships of the new generation with copies of the all-time best. Champions
+// are dealt out to the islands in turn, so no single island loses all its newcomers
+fn reinject_hall_of_fame(ships: &mut [Ship], hall_of_fame: &hall_of_fame::HallOfFame<Ship>, spread: f32) {
+    let mut slots: Vec<Vec<usize>> = vec![vec![]; ships.iter().map(|ship| ship.island + 1).max().unwrap_or(0)];
+    for (index, ship) in ships.iter().enumerate() {
+        slots[ship.island].push(index);
+    }
+    slots.retain(|island| !island.is_empty());
+    let island_count = slots.len();
+    if island_count == 0 {
+        return;
+    }
+
+    for (n, champion) in hall_of_fame.champions(hall_of_fame.reinject_count).into_iter().enumerate() {
+        let index = match slots[n % island_count].pop() {
+            Some(index) => index,
+            None => continue,
+        };
+        let slot = &mut ships[index];
+        let island = slot.island;
+        *slot = champion;
+        slot.island = island;
+        slot.reset(spread);
+    }
+}
+
+// Replaces the population with noisy copies of a saved controller
+fn seed_from_champion(ships: &mut [Ship], scenario: &scenario::Scenario, path: &str, noise: f32) {
+    let controller = match std::fs::read_to_string(path).ok().and_then(|text| controller_from_text(&text)) {
+        Some(controller) => controller,
+        None => {
+            println!("Could not load champion {}, starting from fresh networks", path);
+            return;
+        },
+    };
+    let fresh = &ships[0].neural_net;
+    if controller.0.input_size() != fresh.input_size() || controller.0.output_size() != fresh.output_size() {
+        println!("Champion {} does not fit this ship and observation, starting from fresh networks", path);
+        return;
+    }
+    seed_population(ships, scenario, controller, noise);
+}
+
+// Every ship gets the controller with noise on its weights, the first ship keeps it as is
+fn seed_population(ships: &mut [Ship], scenario: &scenario::Scenario, controller: (neural_net, observation::Normalizer), noise: f32) {
+    for (index, ship) in ships.iter_mut().enumerate() {
+        let mut seeded = Ship::with_controller(scenario, controller.clone());
+        if index > 0 {
+            seeded.neural_net = seeded.neural_net.with_noise(noise);
+        }
+        seeded.reset(0.);
+        *ship = seeded;
+    }
+}
+
+// Flies the baseline pilot through training episodes and records what it does
+fn record_baseline(scenario: &scenario::Scenario, episodes: usize, steps: i32, spread: f32) -> (imitation::Demonstrations, observation::Normalizer) {
+    let mut ship = Ship::new(scenario);
+    ship.pilot = imitation::Pilot::Baseline;
+    ship.recording = Some(imitation::Demonstrations::default());
+    for episode in 0..episodes {
+        ship.reset(spread);
+        for step_n in 0..(steps as usize) {
+            ship.step(&episode_goal(scenario, step_n, steps, spread, episode as i32), scenario);
+        }
+    }
+    (ship.recording.unwrap_or_default(), ship.normalizer)
+}
+
+// Fits a fresh network to the demonstrations and seeds the population with it. Without a
+// path the baseline pilot is recorded first.
+fn pretrain(ships: &mut [Ship], scenario: &scenario::Scenario, config: &imitation::PretrainConfig, demonstrations_path: Option<&str>, steps: i32, spread: f32) {
+    let (demonstrations, normalizer) = match demonstrations_path {
+        Some(path) => match imitation::Demonstrations::load(path) {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                println!("Could not load demonstrations {}: {}", path, e);
+                return;
+            },
+        },
+        None => record_baseline(scenario, config.baseline_episodes, steps, spread),
+    };
+    let mut net = ships[0].neural_net.clone();
+    match imitation::train(&mut net, &demonstrations, config) {
+        Some(loss) => {
+            println!("Pretrained on {} samples, loss {}", demonstrations.len(), loss);
+            seed_population(ships, scenario, (net, normalizer), CHAMPION_SEED_NOISE);
+        },
+        None => println!("Demonstrations do not fit this ship and observation, starting from fresh networks"),
+    }
+}
+
+// Lets a human fly one ship with the arrow keys and returns what was recorded. The ship
+// starts over when it crashes, lands or the episode ends.
+fn iterate_pilot(scenario: &scenario::Scenario, normalizer: observation::Normalizer, steps: i32) -> (imitation::Demonstrations, observation::Normalizer) {
+    let mut window = Window::new(
+        "Raqote",
+        WIDTH,
+        HEIGHT,
+        WindowOptions { ..WindowOptions::default() },
+    ).unwrap();
+    let size = window.get_size();
+    let mut dt = DrawTarget::new(size.0 as i32, size.1 as i32);
+    window.limit_update_rate(Some(std::time::Duration::from_secs_f32(scenario.physics.control_dt())));
+
+    let mut ship = Ship::new(scenario);
+    ship.reset(0.);
+    ship.normalizer = normalizer;
+    ship.recording = Some(imitation::Demonstrations::default());
+    let mut iteration = 0;
+    let mut mouse_pos_world = point::Vector::new(0., 0.);
+
+    while window.is_open() && !window.is_key_down(Key::Escape) {
+        iteration += 1;
+        dt.clear(SolidSource::from_unpremultiplied_argb(0xff, 0x00, 0x00, 0x00));
+        if let Some(pos) = window.get_mouse_pos(MouseMode::Clamp) {
+            mouse_pos_world = screen_to_world(point::Vector::new(pos.0, pos.1));
+        }
+        draw_wind(&mut dt, &scenario.aero.wind, ship.time);
+        if let Some(terrain) = &scenario.terrain {
+            draw_terrain(&mut dt, terrain);
+        }
+        draw_obstacles(&mut dt, &scenario.obstacles);
+
+        ship.pilot = imitation::Pilot::Human(imitation::PilotKeys {
+            up: window.is_key_down(Key::Up),
+            down: window.is_key_down(Key::Down),
+            left: window.is_key_down(Key::Left),
+            right: window.is_key_down(Key::Right),
+        });
+        ship.step(&scenario.fixed_goal().unwrap_or(mouse_pos_world.clone()), scenario);
+        ship.draw(&mut dt);
+
+        if iteration == steps || !ship.is_flying() {
+            ship.reset(0.);
+            iteration = 0;
+        }
+        window.update_with_buffer(dt.get_data(), size.0, size.1).unwrap();
+    }
+    (ship.recording.unwrap_or_default(), ship.normalizer)
+}
+
 fn main() {
+    let mut scenario = scenario::Scenario::default();
+    let mut rigid_body = false;
+    let mut champion_seed: Option<String> = None;
+    // Outer None: no pretraining, inner None: imitate the baseline pilot
+    let mut pretraining: Option<Option<String>> = None;
+    let mut pretrain_config = imitation::PretrainConfig::default();
+    let mut pilot = false;
+    let mut stopping_criteria = stopping::StoppingCriteria::default();
+    for arg in std::env::args() {
+        // --max-generations=, --target-score=, --patience=, --min-improvement=, --time-budget=
+        // and --min-diversity=, see `StoppingCriteria::set`
+        if let Some(result) = stopping_criteria.set(&arg) {
+            if let Err(e) = result {
+                println!("{}, keeping the default", e);
+            }
+            continue;
+        }
+        match arg.as_str() {
+            "--rigid-body" => rigid_body = true,
+            // --ship=<twin|single|quad|asymmetric> or --ship=<path of a design file>
+            _ if arg.starts_with("--ship=") => match ship_design::ShipDesign::named_or_loaded(&arg["--ship=".len()..]) {
+                Ok(design) => scenario.ship = Arc::new(design),
+                Err(e) => println!("Could not load ship {}, using the default ship", e),
+            },
+            "--realistic-motors" => scenario.motors = motor::MotorConfig::realistic(),
+            // --wind or --wind=<calm|constant|gusts|turbulence|shear>
+            "--wind" => scenario.aero = wind::AeroConfig::windy("turbulence").unwrap(),
+            _ if arg.starts_with("--wind=") => match wind::AeroConfig::windy(&arg["--wind=".len()..]) {
+                Some(aero) => scenario.aero = aero,
+                None => println!("Unknown wind {}, using calm air", &arg["--wind=".len()..]),
+            },
+            "--terrain" => scenario.terrain = Some(terrain::Terrain::lunar()),
+            "--landing" => {
+                scenario.terrain = Some(terrain::Terrain::lunar());
+                scenario.task = scenario::Task::Landing;
+            },
+            "--obstacles" => scenario.obstacles = obstacle::course().0,
+            "--navigation" => {
+                let (obstacles, goal) = obstacle::course();
+                scenario.obstacles = obstacles;
+                scenario.task = scenario::Task::Navigation { goal };
+            },
+            "--fuel" => {
+                scenario.fuel.enabled = true;
+                scenario.fuel.score_weight = 100.;
+            },
+            "--randomize" => scenario.randomization = Arc::new(randomization::DomainRandomization::wide()),
+            "--normalized-observations" => scenario.observation = observation::ObservationConfig::normalized(),
+            "--noisy-sensors" => scenario.sensors = sensor::SensorConfig::noisy(obstacle::RAY_COUNT),
+            "--velocity-verlet" => scenario.physics.integrator = integrator::IntegratorKind::VelocityVerlet,
+            "--rk4" => scenario.physics.integrator = integrator::IntegratorKind::Rk4,
+            // --init=<uniform|xavier|he|orthogonal|zeros|small-normal>
+            _ if arg.starts_with("--init=") => match neural::Initializer::named(&arg["--init=".len()..]) {
+                Some(initializer) => scenario.initializer = initializer,
+                None => println!("Unknown initializer {}, using uniform weights", &arg["--init=".len()..]),
+            },
+            // --seed-champion starts from the saved champion, --seed-champion=<path> from any controller file
+            "--seed-champion" => champion_seed = Some(CHAMPION_PATH.to_string()),
+            _ if arg.starts_with("--seed-champion=") => champion_seed = Some(arg["--seed-champion=".len()..].to_string()),
+            // --pretrain imitates the baseline pilot, --pretrain=<path> recorded demonstrations
+            "--pretrain" => pretraining = Some(None),
+            // --pretrain-optimizer=<sgd|adam>
+            _ if arg.starts_with("--pretrain-optimizer=") => match neural::Optimizer::named(&arg["--pretrain-optimizer=".len()..]) {
+                Some(optimizer) => pretrain_config.optimizer = optimizer,
+                None => println!("Unknown optimizer {}, using adam", &arg["--pretrain-optimizer=".len()..]),
+            },
+            _ if arg.starts_with("--pretrain=") => pretraining = Some(Some(arg["--pretrain=".len()..].to_string())),
+            // Fly a ship with the arrow keys instead of training, the flight is added to the demonstrations
+            "--pilot" => pilot = true,
+            _ => {},
+        }
+    }
+    // The inertia depends on the ship design
+    if rigid_body {
+        scenario.physics.body_model = physics::BodyModel::rigid(1., &scenario.ship);
+    }
+
+    if pilot {
+        // Earlier flights are kept and their input statistics go on from where they were
+        let (mut demonstrations, normalizer) = imitation::Demonstrations::load(DEMONSTRATIONS_PATH).unwrap_or_default();
+        let (flown, normalizer) = iterate_pilot(&scenario, normalizer, scenario.physics.control_ticks(1000. / 60.));
+        demonstrations.extend(flown);
+        match demonstrations.save(DEMONSTRATIONS_PATH, &normalizer) {
+            Ok(()) => println!("Saved {} samples to {}", demonstrations.len(), DEMONSTRATIONS_PATH),
+            Err(e) => println!("Could not save demonstrations: {}", e),
+        }
+        return;
+    }
+
     // Vector of ships
     let mut ships: Vec<Ship> = Vec::new();
     // Add 10 ships to vector
     for _ in 0..1000 {
-        let mut ship = Ship::new();
+        let mut ship = Ship::new(&scenario);
         ship.reset(0.);
         ships.push(ship);
     }
+    if let Some(path) = &champion_seed {
+        seed_from_champion(&mut ships, &scenario, path, CHAMPION_SEED_NOISE);
+    }
 
     // let mut spread: f32 = 0.;
     // let mut spread: f32 = 3.;
     // let mut spread: f32 = 0.;
     let mut spread: f32 = 1.;
+    if let Some(demonstrations_path) = &pretraining {
+        // Episodes as long as the first generation's
+        pretrain(&mut ships, &scenario, &pretrain_config, demonstrations_path.as_deref(), scenario.physics.control_ticks(200. / 60.), spread);
+    }
 
     let mut lr: f32 = 0.05;
+    // Training mode can be picked with the first command line argument
+    let mut mode = match std::env::args().nth(1).as_deref() {
+        Some("novelty") => TrainingMode::Novelty(novelty::NoveltyArchive::new(15, 0.01, 2000)),
+        Some("map-elites") => TrainingMode::MapElites(map_elites::MapElites::new(16)),
+        Some("neat") => TrainingMode::Neat(neat::NeatPopulation::new(
+            ships[0].neural_net.input_size(),
+            ships[0].neural_net.output_size(),
+            neat::NeatConfig::default(),
+        )),
+        Some("cmaes") => TrainingMode::CmaEs(cmaes::CmaEs::new(
+            &ships[0].neural_net.get_weights_flat(),
+            0.5,
+            ships.len(),
+        )),
+        Some("nes") => TrainingMode::Nes(nes::Nes::new(
+            &ships[0].neural_net.get_weights_flat(),
+            0.1,
+            0.05,
+            ships.len(),
+        )),
+        Some("ppo") => TrainingMode::PolicyGradient(policy_gradient::PolicyGradient::new(
+            &ships[0].neural_net,
+            policy_gradient::PolicyGradientConfig::ppo(),
+        )),
+        Some("reinforce") => TrainingMode::PolicyGradient(policy_gradient::PolicyGradient::new(
+            &ships[0].neural_net,
+            policy_gradient::PolicyGradientConfig::reinforce(),
+        )),
+        Some("islands") => TrainingMode::Islands(island::IslandModel::new(
+            vec![0.02, 0.05, 0.05, 0.1],
+            10,
+            5,
+        )),
+        _ => TrainingMode::Genetic,
+    };
+    match &mut mode {
+        TrainingMode::Neat(population) => {
+            for ship in &mut ships {
+                ship.genome = Some(population.new_genome());
+            }
+        },
+        TrainingMode::CmaEs(strategy) => {
+            let samples = strategy.ask();
+            ships.truncate(samples.len());
+            set_ship_samples(&mut ships, samples, 0.);
+        },
+        TrainingMode::Nes(strategy) => {
+            let samples = strategy.ask();
+            ships.truncate(samples.len());
+            set_ship_samples(&mut ships, samples, 0.);
+        },
+        TrainingMode::Islands(model) => {
+            for (index, ship) in ships.iter_mut().enumerate() {
+                ship.island = index % model.island_count();
+            }
+        },
+        TrainingMode::PolicyGradient(trainer) => {
+            ships.truncate(trainer.config.episodes);
+            trainer.normalizer = ships[0].normalizer.clone();
+            start_rollouts(&mut ships, trainer, 0.);
+        },
+        _ => {},
+    }
+    let mut hall_of_fame: hall_of_fame::HallOfFame<Ship> = hall_of_fame::HallOfFame::new(20, 10, 5);
+
+    let mut monitor = stopping::TrainingMonitor::new(stopping_criteria);
+    let mut stop_reason = stopping::StopReason::MaxGenerations(monitor.criteria.max_generations);
+
     let mut steps = 5000;
     let mut step_n = 0;
     // let spread: f32 = 0.;
-    for i in 0..100 {
-        steps = 200 + i * 10;
+    for i in 0..(monitor.criteria.max_generations as i32) {
+        // Episode length is in seconds so changing the control rate keeps it the same
+        let episode_seconds = (200 + i * 10) as f32 / 60.;
+        steps = scenario.physics.control_ticks(episode_seconds);
         // spread = ((i as f32 - 100.).max(0.) / 1000.).min(4.);
         // spread = 3.;
         // if (i < 50) {
@@ -578,14 +1778,381 @@ fn main() {
         // } else {
         //     lr = 0.002
         // }
-        iterate_raw(&mut ships, steps, spread, lr, step_n, i);
+        iterate_raw(&mut ships, &mode, &scenario, steps, spread, lr, step_n);
+        update_hall_of_fame(&mut hall_of_fame, &ships, &scenario, steps, spread, step_n);
+        // Ships are sorted by score after the evaluation. Every ship flies the same policy, so there is no diversity to watch
+        let diversity = match mode {
+            TrainingMode::PolicyGradient(_) => None,
+            _ => population_diversity(&ships),
+        };
+        let progress_score = fixed_length_score(&ships[0], &scenario, spread);
+        if let Some(reason) = monitor.check(progress_score, diversity) {
+            stop_reason = reason;
+            break;
+        }
+        next_generation(&mut ships, &mut mode, scenario.physics.episode_seconds(steps), 0., lr);
+        if mode.accepts_reinjection() {
+            reinject_hall_of_fame(&mut ships, &hall_of_fame, 0.);
+        }
         step_n += 1;
     }
 
-    steps = 1000;
+    println!("Training stopped: {}", stop_reason);
+
+    // Neat ships fly their genomes, their networks are untrained and would overwrite the
+    // hall of fame of earlier runs
+    let trains_neural_net = !matches!(mode, TrainingMode::Neat(_));
+    if trains_neural_net {
+        if let Err(e) = hall_of_fame.save(HALL_OF_FAME_PATH, Ship::controller_text) {
+            println!("Could not save hall of fame: {}", e);
+        }
+    } else {
+        println!("Neat genomes are not saved, {} and {} were left as they were", HALL_OF_FAME_PATH, CHAMPION_PATH);
+    }
+    // `--seed-champion` reads this file, so it only ever gets a trained network
+    if let (true, Some(champion)) = (trains_neural_net, hall_of_fame.champions(1).first()) {
+        if let Err(e) = std::fs::write(CHAMPION_PATH, champion.controller_text()) {
+            println!("Could not save champion: {}", e);
+        }
+    }
+
+    steps = scenario.physics.control_ticks(1000. / 60.);
+
+    if let TrainingMode::MapElites(grid) = &mode {
+        // Show the best elites of the grid instead of the last generation
+        match grid.export("map_elites.txt").and_then(|_| map_elites::load_exported("map_elites.txt")) {
+            Ok(elites) => {
+                ships = elites.into_iter().map(|controller| Ship::with_controller(&scenario, controller)).collect();
+            },
+            Err(e) => println!("Could not export map elites: {}", e),
+        }
+    }
+
+    // Show the all-time best networks when training used them (neat genomes are not saved)
+    let uses_neural_net = !matches!(mode, TrainingMode::Neat(_) | TrainingMode::MapElites(_));
+    if let (true, Ok(saved)) = (
+        uses_neural_net,
+        hall_of_fame::HallOfFame::load(HALL_OF_FAME_PATH, 10, controller_from_text),
+    ) {
+        ships = saved.champions(10).into_iter().map(|controller| Ship::with_controller(&scenario, controller)).collect();
+    }
 
     // Truncate ships to 50
     ships.truncate(10);
 
-    iterate_draw(&mut ships, steps, spread, lr);
+    iterate_draw(&mut ships, &scenario, steps, spread, lr);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Spins the twin ship up with opposite thrust at both ends and returns the largest
+    // deviation of its link from the rest length of 1
+    fn spinning_link_drift(kind: integrator::IntegratorKind, steps: usize) -> f32 {
+        let physics = physics::PhysicsConfig {
+            gravity: 0.,
+            integrator: kind,
+            ..physics::PhysicsConfig::default()
+        };
+        let mut ship = Ship::new(&scenario::Scenario::default());
+        ship.motors[0].throttle = 0.01;
+        ship.motors[1].throttle = 0.01;
+        ship.motors[0].angle = 0.;
+        ship.motors[1].angle = std::f32::consts::PI;
+
+        let mut worst: f32 = 0.;
+        for _ in 0..steps {
+            ship.simulate(&physics, &wind::AeroConfig::default());
+            assert!(!ship.dead);
+            worst = worst.max((ship.axis().length() - 1.).abs());
+        }
+        worst
+    }
+
+    #[test]
+    fn link_length_stays_constant_over_long_runs() {
+        for kind in [
+            integrator::IntegratorKind::SemiImplicitEuler,
+            integrator::IntegratorKind::VelocityVerlet,
+            integrator::IntegratorKind::Rk4,
+        ] {
+            let drift = spinning_link_drift(kind, 20_000);
+            assert!(drift < 1e-3, "{:?} link drifted by {}", kind, drift);
+        }
+    }
+
+    #[test]
+    fn ship_actually_spins_in_drift_test() {
+        let physics = physics::PhysicsConfig { gravity: 0., ..physics::PhysicsConfig::default() };
+        let mut ship = Ship::new(&scenario::Scenario::default());
+        ship.motors[0].throttle = 0.01;
+        ship.motors[1].throttle = 0.01;
+        ship.motors[1].angle = std::f32::consts::PI;
+        for _ in 0..1200 {
+            ship.simulate(&physics, &wind::AeroConfig::default());
+        }
+        let axis = ship.axis();
+        // Started horizontal, ten seconds of torque turn it well away from that
+        assert!(axis.y.abs() > 0.1, "ship did not rotate: {:?}", axis);
+        // Pure torque, the center stays put
+        assert!(ship.center().length() < 1e-3);
+    }
+
+    // Score of one second of navigation towards a goal beside the start with the given
+    // physics steps per control tick
+    fn navigation_score(substeps: u32) -> f32 {
+        let goal = point::Vector::new(2., 0.);
+        let scenario = scenario::Scenario {
+            physics: physics::PhysicsConfig { substeps, ..physics::PhysicsConfig::default() },
+            initializer: neural::Initializer::Zeros,
+            task: scenario::Task::Navigation { goal: goal.clone() },
+            ..scenario::Scenario::default()
+        };
+        let mut ship = Ship::new(&scenario);
+        for _ in 0..scenario.physics.control_ticks(1.) {
+            ship.step(&goal, &scenario);
+        }
+        ship.score
+    }
+
+    #[test]
+    fn score_does_not_depend_on_the_control_rate() {
+        let slow = navigation_score(2);
+        let fast = navigation_score(1);
+        // About two metres from the goal for one second
+        assert!(slow > 1.5 && slow < 3., "score {}", slow);
+        assert!((slow - fast).abs() < 0.05 * slow, "{} at 60 Hz, {} at 120 Hz", slow, fast);
+    }
+
+    #[test]
+    fn tracking_objective_leaves_out_the_fuel_cost() {
+        let goal = point::Vector::new(2., 0.);
+        let scenario = scenario::Scenario {
+            initializer: neural::Initializer::Zeros,
+            task: scenario::Task::Navigation { goal: goal.clone() },
+            fuel: fuel::FuelConfig { enabled: true, score_weight: 100., ..fuel::FuelConfig::default() },
+            ..scenario::Scenario::default()
+        };
+        // Blank networks fly at half throttle
+        let mut ship = Ship::new(&scenario);
+        for _ in 0..20 {
+            ship.step(&goal, &scenario);
+        }
+        assert!(ship.fuel_used > 0., "no fuel burned");
+        let fuel_cost = ship.fuel_used * scenario.fuel.score_weight;
+        assert!((ship.objectives.tracking - (ship.score - fuel_cost)).abs() < 1e-3,
+            "tracking {} score {} fuel cost {}", ship.objectives.tracking, ship.score, fuel_cost);
+    }
+
+    // How far a steady side wind pushes a ship without gravity or thrust in ten steps
+    fn wind_push(body_model: physics::BodyModel, mass_scale: f32) -> f32 {
+        let physics = physics::PhysicsConfig { gravity: 0., body_model, ..physics::PhysicsConfig::default() };
+        let aero = wind::AeroConfig { linear_drag: 1., quadratic_drag: 0., wind: wind::WindField::Constant(point::Vector::new(1., 0.)) };
+        let mut ship = Ship::new(&scenario::Scenario::default());
+        ship.conditions.mass_scale = mass_scale;
+        for motor in &mut ship.motors {
+            motor.throttle = 0.;
+        }
+        for _ in 0..10 {
+            ship.simulate(&physics, &aero);
+        }
+        ship.center().x
+    }
+
+    #[test]
+    fn heavier_ships_drift_less_in_both_body_models() {
+        let rigid = physics::BodyModel::rigid(1., &ship_design::ShipDesign::default());
+        for body_model in [physics::BodyModel::PointMasses, rigid] {
+            let light = wind_push(body_model.clone(), 1.);
+            let heavy = wind_push(body_model.clone(), 2.);
+            assert!(light > 0., "{:?} did not move", body_model);
+            assert!((heavy / light - 0.5).abs() < 0.01, "{:?}: {} at twice the mass, {} at the normal one", body_model, heavy, light);
+        }
+        let points = wind_push(physics::BodyModel::PointMasses, 1.3);
+        let rigid = wind_push(physics::BodyModel::rigid(1., &ship_design::ShipDesign::default()), 1.3);
+        assert!((points - rigid).abs() < 0.01 * points, "point masses {}, rigid body {}", points, rigid);
+    }
+
+    // Level ship with its points at the given height over x, falling at the given speed
+    fn touch_ground(x: f32, height: f32, speed: f32) -> Ship {
+        let terrain = terrain::Terrain::lunar();
+        let physics = physics::PhysicsConfig::default();
+        let mut ship = Ship::new(&scenario::Scenario::default());
+        ship.points = vec![point::Vector::new(x + 0.5, height), point::Vector::new(x - 0.5, height)];
+        ship.points_last = ship.points.iter().map(|point| point.subtracted(&point::Vector::new(0., speed * physics.dt))).collect();
+        ship.check_ground(&terrain, &physics);
+        ship
+    }
+
+    #[test]
+    fn ground_contact_lands_or_crashes() {
+        let landed = touch_ground(1.75, 3.01, 1.);
+        let touchdown = landed.touchdown.clone().expect("no touchdown");
+        assert!(touchdown.landed_on(0), "{:?}", touchdown);
+        assert!(!landed.dead);
+        // Put back on the surface
+        assert!(landed.points.iter().all(|point| point.y <= 3. + 1e-5));
+
+        let crashed = touch_ground(1.75, 3.01, 3.);
+        assert_eq!(crashed.touchdown.map(|touchdown| touchdown.kind), Some(terrain::TouchdownKind::Crashed));
+        assert!(crashed.dead);
+
+        // Nozzles hang below the points, well above the ground nothing happens
+        assert!(touch_ground(1.75, 2., 3.).touchdown.is_none());
+    }
+
+    // Two points on a 30 degree slope with a motor on each, the first point lower
+    fn sloped_design() -> ship_design::ShipDesign {
+        ship_design::ShipDesign::from_text("point 0.433 0.25\npoint -0.433 -0.25\nlink 0 1\nthruster 0 1 1\nthruster 1 1 1").unwrap()
+    }
+
+    #[test]
+    fn tilt_is_measured_from_the_design() {
+        let scenario = scenario::Scenario { ship: Arc::new(sloped_design()), ..scenario::Scenario::default() };
+        let mut ship = Ship::new(&scenario);
+        ship.reset(0.);
+        assert!(ship.tilt().abs() < 1e-5, "tilted by {} at rest", ship.tilt());
+        // Motors push straight up as designed
+        let up = ship.motor_direction(0.);
+        assert!(up.x.abs() < 1e-5 && (up.y + 1.).abs() < 1e-5, "{:?}", up);
+
+        // Turning the first point further down tilts it positively, for the level twin as well
+        for design in [sloped_design(), ship_design::ShipDesign::twin()] {
+            let scenario = scenario::Scenario { ship: Arc::new(design), ..scenario::Scenario::default() };
+            let mut ship = Ship::new(&scenario);
+            ship.reset(0.);
+            let body = rigid_body::RigidBody::from_points(&ship.points, &ship.points, 1., 1., 1.);
+            let turned = rigid_body::RigidBody { angle: body.angle + 0.2, ..body };
+            let local = ship.design.local_points();
+            ship.points_last = ship.points.clone();
+            ship.points = local.iter().map(|point| turned.world_point(point)).collect();
+            assert!((ship.tilt() - 0.2).abs() < 1e-4, "tilt {}", ship.tilt());
+            let dt = 0.01;
+            let state = ship.flight_state(&point::Vector::new(0., 0.), dt);
+            assert!((state.tilt_rate - 0.2 / dt).abs() < 0.1, "tilt rate {}", state.tilt_rate);
+        }
+    }
+
+    #[test]
+    fn sloped_design_lands_at_rest() {
+        let terrain = terrain::Terrain::lunar();
+        let physics = physics::PhysicsConfig::default();
+        let scenario = scenario::Scenario { ship: Arc::new(sloped_design()), ..scenario::Scenario::default() };
+        let mut ship = Ship::new(&scenario);
+        ship.reset(0.);
+        // Gently onto the pad as it was designed
+        let drop = point::Vector::new(1.75, 3. - ship.center().y - 0.2);
+        ship.points = ship.points.iter().map(|point| point.added(&drop)).collect();
+        ship.points_last = ship.points.iter().map(|point| point.subtracted(&point::Vector::new(0., 0.005))).collect();
+        ship.check_ground(&terrain, &physics);
+        let touchdown = ship.touchdown.expect("no touchdown");
+        assert!(touchdown.landed_on(0), "{:?}", touchdown);
+    }
+
+    #[test]
+    fn engines_stop_when_the_tank_runs_dry() {
+        let fuel = fuel::FuelConfig { enabled: true, ..fuel::FuelConfig::default() };
+        let physics = physics::PhysicsConfig { gravity: 0., ..physics::PhysicsConfig::default() };
+        let mut ship = Ship::new(&scenario::Scenario::default());
+        // Just enough fuel left for one step at full throttle
+        ship.fuel_used = fuel.capacity - fuel.burned(2., physics.dt) * 0.5;
+        for motor in &mut ship.motors {
+            motor.throttle = 1.;
+        }
+        ship.burn_fuel(&fuel, physics.dt);
+        assert!(ship.motors.iter().all(|motor| motor.throttle == 0.));
+        assert!((ship.thrust_scale - 1. / fuel.dry_mass).abs() < 1e-4, "thrust scale {}", ship.thrust_scale);
+
+        let start = ship.center();
+        for _ in 0..10 {
+            ship.burn_fuel(&fuel, physics.dt);
+            ship.simulate(&physics, &wind::AeroConfig::default());
+        }
+        assert!(ship.center().subtracted(&start).length() < 1e-6, "moved without fuel");
+        assert!(ship.fuel_used <= fuel.capacity + fuel.burned(2., physics.dt));
+    }
+
+    #[test]
+    fn reinjected_champions_are_spread_over_the_islands() {
+        let scenario = scenario::Scenario::default();
+        let mut ships: Vec<Ship> = (0..6).map(|n| {
+            let mut ship = Ship::new(&scenario);
+            ship.island = n / 3;
+            ship
+        }).collect();
+        let mut hall_of_fame = hall_of_fame::HallOfFame::new(5, 0, 2);
+        for (n, champion) in [Ship::new(&scenario), Ship::new(&scenario)].iter().enumerate() {
+            hall_of_fame.consider(champion, n as f32, 0, Ship::same_controller);
+        }
+
+        reinject_hall_of_fame(&mut ships, &hall_of_fame, 0.);
+        let champions = hall_of_fame.champions(2);
+        // The last ship of each island is replaced and keeps its island
+        assert!(ships[2].same_controller(&champions[0]) && ships[2].island == 0);
+        assert!(ships[5].same_controller(&champions[1]) && ships[5].island == 1);
+        assert_eq!(ships.iter().filter(|ship| champions.iter().any(|c| c.same_controller(ship))).count(), 2);
+    }
+
+    #[test]
+    fn wrap_angle_takes_the_short_way_round() {
+        use std::f32::consts::PI;
+        assert!((point::wrap_angle(0.3) - 0.3).abs() < 1e-6);
+        assert!((point::wrap_angle(-3.1 - 3.1) - (2. * PI - 6.2)).abs() < 1e-5);
+        assert!((point::wrap_angle(3.1 - -3.1) - (6.2 - 2. * PI)).abs() < 1e-5);
+        assert!((point::wrap_angle(5. * PI + 0.1) - (-PI + 0.1)).abs() < 1e-5);
+        for angle in [-1e-9, PI, -PI, 7. * PI, -7. * PI] {
+            assert!(point::wrap_angle(angle).abs() <= PI, "{} wrapped out of range", angle);
+        }
+    }
+
+    #[test]
+    fn angular_velocity_is_small_across_the_branch_cut() {
+        let dt = physics::PhysicsConfig::default().dt;
+        let mut ship = Ship::new(&scenario::Scenario::default());
+        // Axis pointing straight up, where `Vector::angle` jumps from pi to -pi
+        let step = 0.001;
+        ship.points_last = vec![point::Vector::new(step, -0.5), point::Vector::new(-step, 0.5)];
+        ship.points = vec![point::Vector::new(-step, -0.5), point::Vector::new(step, 0.5)];
+        assert!(ship.axis_last().angle() > 3. && ship.heading() < -3.);
+
+        let expected = 2. * (2. * step).atan2(1.) / dt;
+        let velocity = ship.angular_velocity(dt);
+        assert!((velocity - expected).abs() < 1e-3 * expected, "{} instead of {}", velocity, expected);
+
+        // Turning back the other way flips the sign
+        std::mem::swap(&mut ship.points, &mut ship.points_last);
+        assert!((ship.angular_velocity(dt) + expected).abs() < 1e-3 * expected);
+    }
+
+    #[test]
+    fn angular_velocity_stays_smooth_over_full_turns() {
+        let physics = physics::PhysicsConfig { gravity: 0., ..physics::PhysicsConfig::default() };
+        let mut ship = Ship::new(&scenario::Scenario::default());
+        ship.motors[0].throttle = 0.1;
+        ship.motors[1].throttle = 0.1;
+        ship.motors[1].angle = std::f32::consts::PI;
+
+        let mut turned = 0.;
+        let mut last_velocity = 0.;
+        for _ in 0..3000 {
+            ship.simulate(&physics, &wind::AeroConfig::default());
+            let velocity = ship.angular_velocity(physics.dt);
+            // Constant torque, the turn rate only ever changes a little per step
+            assert!((velocity - last_velocity).abs() < 0.1, "jumped from {} to {}", last_velocity, velocity);
+            turned += velocity * physics.dt;
+            last_velocity = velocity;
+        }
+        // Several full turns, so the branch cut was crossed many times
+        assert!(turned.abs() > 4. * std::f32::consts::PI, "only turned {}", turned);
+    }
+
+    #[test]
+    fn zeros_population_survives_the_first_check() {
+        let scenario = scenario::Scenario { initializer: neural::Initializer::Zeros, ..scenario::Scenario::default() };
+        let ships: Vec<Ship> = (0..10).map(|_| Ship::new(&scenario)).collect();
+        assert_eq!(population_diversity(&ships), Some(0.));
+        let mut monitor = stopping::TrainingMonitor::new(stopping::StoppingCriteria::default());
+        assert!(monitor.check(1., population_diversity(&ships)).is_none());
+    }
 }
@@ -0,0 +1,111 @@
+use std::fs;
+use crate::neural::neural_net;
+use crate::novelty::DESCRIPTOR_SIZE;
+use crate::observation::Normalizer;
+
+#[derive(Clone)]
+pub struct Elite {
+    pub neural_net: neural_net,
+    // Input statistics the network was scored with
+    pub normalizer: Normalizer,
+    // Per second of the episode, episodes of different length compare fairly
+    pub score: f32,
+    pub descriptor: [f32; DESCRIPTOR_SIZE],
+}
+
+// Grid of the best (lowest score) controller found for each behaviour cell.
+// The grid is spanned by average tilt and average throttle.
+pub struct MapElites {
+    resolution: usize,
+    tilt_range: (f32, f32),
+    throttle_range: (f32, f32),
+    cells: Vec<Option<Elite>>,
+}
+
+fn bucket(value: f32, range: (f32, f32), resolution: usize) -> usize {
+    let normalized = (value - range.0) / (range.1 - range.0);
+    ((normalized * resolution as f32) as isize).clamp(0, resolution as isize - 1) as usize
+}
+
+impl MapElites {
+    pub fn new(resolution: usize) -> MapElites {
+        MapElites {
+            resolution,
+            tilt_range: (-std::f32::consts::PI / 2., std::f32::consts::PI / 2.),
+            throttle_range: (0., 1.),
+            cells: vec![None; resolution * resolution],
+        }
+    }
+
+    fn cell_index(&self, descriptor: &[f32; DESCRIPTOR_SIZE]) -> usize {
+        let tilt_bucket = bucket(descriptor[2], self.tilt_range, self.resolution);
+        let throttle_bucket = bucket(descriptor[3], self.throttle_range, self.resolution);
+        tilt_bucket * self.resolution + throttle_bucket
+    }
+
+    // Returns true if the candidate took over its cell
+    pub fn insert(&mut self, neural_net: &neural_net, normalizer: &Normalizer, score: f32, descriptor: [f32; DESCRIPTOR_SIZE]) -> bool {
+        let index = self.cell_index(&descriptor);
+        let better = match &self.cells[index] {
+            None => true,
+            Some(elite) => score < elite.score,
+        };
+        if better {
+            self.cells[index] = Some(Elite {
+                neural_net: neural_net.clone(),
+                normalizer: normalizer.clone(),
+                score,
+                descriptor,
+            });
+        }
+        better
+    }
+
+    pub fn elites(&self) -> Vec<&Elite> {
+        self.cells.iter().flatten().collect()
+    }
+
+    pub fn filled_cells(&self) -> usize {
+        self.cells.iter().filter(|cell| cell.is_some()).count()
+    }
+
+    // Writes every elite as a header line (tilt bucket, throttle bucket, score, descriptor)
+    // followed by the network and its input statistics
+    pub fn export(&self, path: &str) -> std::io::Result<()> {
+        let mut text = String::new();
+        for (index, cell) in self.cells.iter().enumerate() {
+            if let Some(elite) = cell {
+                text.push_str(&format!(
+                    "elite {} {} {} {}\n",
+                    index / self.resolution,
+                    index % self.resolution,
+                    elite.score,
+                    elite.descriptor.iter().map(|value| value.to_string()).collect::<Vec<String>>().join(" ")
+                ));
+                text.push_str(&elite.neural_net.to_text());
+                text.push('\n');
+                text.push_str(&elite.normalizer.to_text());
+                text.push('\n');
+            }
+        }
+        fs::write(path, text)
+    }
+}
+
+// Reads back the networks written by `MapElites::export`, best score first
+pub fn load_exported(path: &str) -> std::io::Result<Vec<(neural_net, Normalizer)>> {
+    let text = fs::read_to_string(path)?;
+    let mut elites: Vec<(f32, (neural_net, Normalizer))> = vec![];
+    for block in text.split("elite ").filter(|block| !block.trim().is_empty()) {
+        let (header, net_text) = match block.split_once('\n') {
+            Some(parts) => parts,
+            None => continue,
+        };
+        let score: f32 = header.split_whitespace().nth(2).and_then(|s| s.parse().ok()).unwrap_or(f32::MAX);
+        if let Some(net) = neural_net::from_text(net_text) {
+            elites.push((score, (net, Normalizer::from_text(net_text).unwrap_or_default())));
+        }
+    }
+    elites.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+    Ok(elites.into_iter().map(|(_, controller)| controller).collect())
+}
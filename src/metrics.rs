@@ -0,0 +1,220 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+use crate::randomization::EpisodeConditions;
+
+pub const METRICS_PATH: &str = "metrics.csv";
+pub const EPISODES_PATH: &str = "episodes.csv";
+// Diagnostics have a row per metric rather than a column, because the metrics depend on the
+// network layout. metrics.csv keeps its fixed columns so runs of every mode line up.
+pub const DIAGNOSTICS_PATH: &str = "diagnostics.csv";
+
+// Network outputs closer than this to 0 or 1 count as saturated
+const SATURATION_MARGIN: f32 = 0.02;
+const OUTPUT_BINS: usize = 10;
+
+// Prints the per-generation summary line and appends it to the metrics csv, so that
+// runs with different training modes can be compared. Returns the average score.
+pub fn log_generation(mode: &str, generation: i32, lr: f32, scores: &[f32], spread: f32) -> f32 {
+    let mut sorted = scores.to_vec();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+    let average_score = sorted.iter().sum::<f32>() / sorted.len() as f32;
+
+    let best_scores = sorted.iter().take(8).copied().collect::<Vec<f32>>();
+    let best_score_string = best_scores.iter().map(|score| score.to_string()).collect::<Vec<String>>().join(" ");
+
+    // Get scores of top 10% of ships
+    let best_scores_of_10_percent = &sorted[..((sorted.len() as f32 * 0.1) as usize).max(1)];
+    let average_of_top_10_percent = best_scores_of_10_percent.iter().sum::<f32>() / best_scores_of_10_percent.len() as f32;
+
+    println!("Average score: {} {} {} {} [{}] {}", generation, lr, average_of_top_10_percent, average_score, best_score_string, spread);
+
+    let written = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(generation != 0)
+        .truncate(generation == 0)
+        .open(METRICS_PATH)
+        .and_then(|mut file| {
+            if generation == 0 {
+                writeln!(file, "mode,generation,lr,top_10_percent,average,best,spread")?;
+            }
+            writeln!(file, "{},{},{},{},{},{},{}", mode, generation, lr, average_of_top_10_percent, average_score, sorted[0], spread)
+        });
+    if let Err(e) = written {
+        println!("Could not write metrics: {}", e);
+    }
+
+    average_score
+}
+
+// Appends one row per episode with its score, whether the ship died and the conditions
+// it was simulated in
+pub fn log_episodes(generation: i32, episodes: &[(f32, bool, &EpisodeConditions)]) {
+    let written = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(generation != 0)
+        .truncate(generation == 0)
+        .open(EPISODES_PATH)
+        .and_then(|mut file| {
+            if generation == 0 {
+                writeln!(file, "generation,score,dead,{}", EpisodeConditions::CSV_HEADER)?;
+            }
+            for (score, dead, conditions) in episodes {
+                writeln!(file, "{},{},{},{}", generation, score, *dead as i32, conditions.csv_fields())?;
+            }
+            Ok(())
+        });
+    if let Err(e) = written {
+        println!("Could not write episodes: {}", e);
+    }
+}
+
+// How a controller used its outputs over an episode
+#[derive(Debug, Clone, Default)]
+pub struct OutputUsage {
+    // Per output, how often its value fell in each tenth of [0, 1]
+    bins: Vec<[u32; OUTPUT_BINS]>,
+    saturated: u32,
+    total: u32,
+}
+
+impl OutputUsage {
+    pub fn record(&mut self, outputs: &[f32]) {
+        self.bins.resize(outputs.len(), [0; OUTPUT_BINS]);
+        for (bins, output) in self.bins.iter_mut().zip(outputs) {
+            let bin = ((output * OUTPUT_BINS as f32) as usize).min(OUTPUT_BINS - 1);
+            bins[bin] += 1;
+            if *output < SATURATION_MARGIN || *output > 1. - SATURATION_MARGIN {
+                self.saturated += 1;
+            }
+            self.total += 1;
+        }
+    }
+
+    pub fn add(&mut self, other: &OutputUsage) {
+        if self.bins.len() < other.bins.len() {
+            self.bins.resize(other.bins.len(), [0; OUTPUT_BINS]);
+        }
+        for (bins, other_bins) in self.bins.iter_mut().zip(&other.bins) {
+            for (count, other_count) in bins.iter_mut().zip(other_bins) {
+                *count += other_count;
+            }
+        }
+        self.saturated += other.saturated;
+        self.total += other.total;
+    }
+
+    pub fn saturation_rate(&self) -> f32 {
+        self.saturated as f32 / self.total.max(1) as f32
+    }
+}
+
+// What the population looks like after a generation
+#[derive(Debug, Clone, Default)]
+pub struct Diagnostics {
+    // Mean and deviation of the weights of every layer over all ships, empty for neat genomes
+    pub layers: Vec<(f32, f32)>,
+    // Average weight distance between two ships of a random sample of the population
+    pub sampled_pairwise_distance: Option<f32>,
+    pub distinct_controllers: usize,
+    pub population: usize,
+    pub output_usage: OutputUsage,
+}
+
+// Prints a summary and appends one "generation,metric,value" row per number
+pub fn log_diagnostics(generation: i32, diagnostics: &Diagnostics) {
+    let mut rows: Vec<(String, f32)> = vec![
+        ("distinct_controllers".to_string(), diagnostics.distinct_controllers as f32),
+        ("population".to_string(), diagnostics.population as f32),
+        ("saturation_rate".to_string(), diagnostics.output_usage.saturation_rate()),
+    ];
+    if let Some(distance) = diagnostics.sampled_pairwise_distance {
+        rows.push(("sampled_pairwise_distance".to_string(), distance));
+    }
+    for (layer, (mean, std)) in diagnostics.layers.iter().enumerate() {
+        rows.push((format!("layer{}_mean", layer), *mean));
+        rows.push((format!("layer{}_std", layer), *std));
+    }
+    // Histograms as fractions of the ticks
+    for (output, bins) in diagnostics.output_usage.bins.iter().enumerate() {
+        let total = bins.iter().sum::<u32>().max(1) as f32;
+        for (bin, count) in bins.iter().enumerate() {
+            rows.push((format!("output{}_bin{}", output, bin), *count as f32 / total));
+        }
+    }
+
+    let layers = diagnostics.layers.iter()
+        .map(|(mean, std)| format!("{:.3}/{:.3}", mean, std))
+        .collect::<Vec<String>>()
+        .join(" ");
+    println!(
+        "Diagnostics: {} of {} controllers distinct, distance {}, {:.1}% outputs saturated, layer mean/std [{}]",
+        diagnostics.distinct_controllers,
+        diagnostics.population,
+        diagnostics.sampled_pairwise_distance.map_or("-".to_string(), |distance| distance.to_string()),
+        diagnostics.output_usage.saturation_rate() * 100.,
+        layers,
+    );
+
+    let written = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(generation != 0)
+        .truncate(generation == 0)
+        .open(DIAGNOSTICS_PATH)
+        .and_then(|mut file| {
+            if generation == 0 {
+                writeln!(file, "generation,metric,value")?;
+            }
+            for (metric, value) in rows {
+                writeln!(file, "{},{},{}", generation, metric, value)?;
+            }
+            Ok(())
+        });
+    if let Err(e) = written {
+        println!("Could not write diagnostics: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn outputs_land_in_their_bins() {
+        let mut usage = OutputUsage::default();
+        usage.record(&[0., 0.1, 0.55]);
+        usage.record(&[0.0999, 1., 0.95]);
+        assert_eq!(usage.bins.len(), 3);
+        assert_eq!(usage.bins[0][0], 2);
+        // 0.1 starts the second bin, 1 goes to the last one
+        assert_eq!(usage.bins[1][1], 1);
+        assert_eq!(usage.bins[1][OUTPUT_BINS - 1], 1);
+        assert_eq!(usage.bins[2][5], 1);
+        assert_eq!(usage.bins[2][9], 1);
+    }
+
+    #[test]
+    fn saturation_counts_outputs_near_the_ends() {
+        let mut usage = OutputUsage::default();
+        usage.record(&[0.01, 0.5, 0.99, 0.5]);
+        assert_eq!((usage.saturated, usage.total), (2, 4));
+        assert_eq!(usage.saturation_rate(), 0.5);
+        assert_eq!(OutputUsage::default().saturation_rate(), 0.);
+    }
+
+    #[test]
+    fn adding_usage_sums_counts_and_grows_outputs() {
+        let mut usage = OutputUsage::default();
+        usage.record(&[0.]);
+        let mut other = OutputUsage::default();
+        other.record(&[0.05, 0.5]);
+        usage.add(&other);
+        assert_eq!(usage.bins.len(), 2);
+        assert_eq!(usage.bins[0][0], 2);
+        assert_eq!(usage.bins[1][5], 1);
+        assert_eq!((usage.saturated, usage.total), (1, 3));
+    }
+}
@@ -0,0 +1,141 @@
+// Actuator model between what the network asks for and what the motors do
+#[derive(Debug, Clone)]
+pub struct MotorConfig {
+    // Time constant of the first order lag on thrust, in seconds. 0 is instant.
+    pub thrust_time_constant: f32,
+    // Fastest the gimbal can turn, in rad/s
+    pub max_gimbal_rate: f32,
+    // Gimbal can turn this far to either side, in radians
+    pub gimbal_range: f32,
+    // Commands below this turn the motor off, the motor cannot run slower than this
+    pub min_throttle: f32,
+}
+
+impl Default for MotorConfig {
+    // Ideal motors, the network output is applied as is
+    fn default() -> MotorConfig {
+        MotorConfig {
+            thrust_time_constant: 0.,
+            max_gimbal_rate: f32::INFINITY,
+            gimbal_range: 1.,
+            min_throttle: 0.,
+        }
+    }
+}
+
+impl MotorConfig {
+    pub fn realistic() -> MotorConfig {
+        MotorConfig {
+            thrust_time_constant: 0.15,
+            max_gimbal_rate: 3.,
+            gimbal_range: 0.4,
+            min_throttle: 0.2,
+        }
+    }
+
+    // Maps a network output in [0, 1] to a gimbal angle command, within the range of both the
+    // actuator and the thruster
+    pub fn angle_command(&self, output: f32, thruster_range: f32) -> f32 {
+        (output - 0.5) * 2.0 * self.gimbal_range.min(thruster_range)
+    }
+
+    pub fn throttle_command(&self, output: f32) -> f32 {
+        if output < self.min_throttle {
+            0.
+        } else {
+            output.min(1.)
+        }
+    }
+
+    // Moves the actual throttle towards the command over one physics step
+    pub fn actuate_throttle(&self, throttle: f32, command: f32, dt: f32) -> f32 {
+        if self.thrust_time_constant <= 0. {
+            return command;
+        }
+        throttle + (command - throttle) * (1. - (-dt / self.thrust_time_constant).exp())
+    }
+
+    // Moves the actual gimbal angle towards the command, limited by the slew rate
+    pub fn actuate_angle(&self, angle: f32, command: f32, dt: f32) -> f32 {
+        let command = command.clamp(-self.gimbal_range, self.gimbal_range);
+        let max_step = self.max_gimbal_rate * dt;
+        angle + (command - angle).clamp(-max_step, max_step)
+    }
+}
+
+// What the network asked a motor to do
+#[derive(Debug, Clone, Default)]
+pub struct MotorCommand {
+    pub throttle: f32,
+    pub angle: f32,
+}
+
+// Where a motor actually is and what it was asked to do
+#[derive(Debug, Clone, Default)]
+pub struct MotorState {
+    pub angle: f32,
+    pub throttle: f32,
+    pub command: MotorCommand,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn throttle_lag_converges_with_its_time_constant() {
+        let motors = MotorConfig::realistic();
+        let dt = 1. / 120.;
+        let mut throttle = 0.;
+        let mut time = 0.;
+        let mut after_one_constant = None;
+        while time < 10. * motors.thrust_time_constant {
+            throttle = motors.actuate_throttle(throttle, 1., dt);
+            time += dt;
+            if after_one_constant.is_none() && time >= motors.thrust_time_constant - 1e-6 {
+                after_one_constant = Some(throttle);
+            }
+        }
+        // 1 - 1/e of the way after one time constant, whatever the step
+        let expected = 1. - (-1f32).exp();
+        assert!((after_one_constant.unwrap() - expected).abs() < 0.01, "{:?}", after_one_constant);
+        assert!((throttle - 1.).abs() < 1e-3, "{}", throttle);
+        // Ideal motors follow at once
+        assert_eq!(MotorConfig::default().actuate_throttle(0., 0.7, dt), 0.7);
+    }
+
+    #[test]
+    fn gimbal_turns_no_faster_than_its_slew_rate() {
+        let motors = MotorConfig::realistic();
+        let dt = 1. / 120.;
+        let mut angle = -motors.gimbal_range;
+        for _ in 0..200 {
+            // Asks for more than the range allows
+            let next = motors.actuate_angle(angle, 2., dt);
+            assert!(next - angle <= motors.max_gimbal_rate * dt + 1e-6, "{} to {}", angle, next);
+            assert!(next <= motors.gimbal_range);
+            angle = next;
+        }
+        assert_eq!(angle, motors.gimbal_range);
+        // Small moves finish in one step
+        assert_eq!(motors.actuate_angle(0.1, 0.11, dt), 0.11);
+    }
+
+    #[test]
+    fn commands_below_min_throttle_turn_the_motor_off() {
+        let motors = MotorConfig::realistic();
+        assert_eq!(motors.throttle_command(0.19), 0.);
+        assert_eq!(motors.throttle_command(0.2), 0.2);
+        assert_eq!(motors.throttle_command(0.7), 0.7);
+        assert_eq!(motors.throttle_command(1.3), 1.);
+        assert_eq!(MotorConfig::default().throttle_command(0.01), 0.01);
+    }
+
+    #[test]
+    fn angle_command_uses_the_narrower_range() {
+        let motors = MotorConfig::realistic();
+        assert_eq!(motors.angle_command(1., 1.), motors.gimbal_range);
+        assert_eq!(motors.angle_command(0., 0.1), -0.1);
+        assert_eq!(motors.angle_command(0.5, 0.1), 0.);
+    }
+}
@@ -0,0 +1,584 @@
+use std::collections::HashMap;
+use rand::Rng;
+
+fn sigmoid(x: f32) -> f32 {
+    1.0 / (1.0 + (-x).exp())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NodeKind {
+    Input,
+    Bias,
+    Output,
+    Hidden,
+}
+
+#[derive(Debug, Clone)]
+pub struct NodeGene {
+    pub id: usize,
+    pub kind: NodeKind,
+}
+
+#[derive(Debug, Clone)]
+pub struct ConnectionGene {
+    pub from: usize,
+    pub to: usize,
+    pub weight: f32,
+    pub enabled: bool,
+    pub innovation: usize,
+}
+
+// Hands out innovation numbers and node ids so that the same structural mutation
+// gets the same numbers in every genome of a run
+pub struct InnovationTracker {
+    next_innovation: usize,
+    next_node_id: usize,
+    connections: HashMap<(usize, usize), usize>,
+    splits: HashMap<usize, usize>,
+}
+
+impl InnovationTracker {
+    pub fn new(input_count: usize, output_count: usize) -> InnovationTracker {
+        InnovationTracker {
+            next_innovation: 0,
+            // Inputs, bias and outputs have fixed ids
+            next_node_id: input_count + 1 + output_count,
+            connections: HashMap::new(),
+            splits: HashMap::new(),
+        }
+    }
+
+    fn connection_innovation(&mut self, from: usize, to: usize) -> usize {
+        if let Some(innovation) = self.connections.get(&(from, to)) {
+            return *innovation;
+        }
+        let innovation = self.next_innovation;
+        self.next_innovation += 1;
+        self.connections.insert((from, to), innovation);
+        innovation
+    }
+
+    fn fresh_node_id(&mut self) -> usize {
+        let id = self.next_node_id;
+        self.next_node_id += 1;
+        id
+    }
+
+    // Node id for splitting the given connection
+    fn split_node_id(&mut self, innovation: usize) -> usize {
+        if let Some(id) = self.splits.get(&innovation) {
+            return *id;
+        }
+        let id = self.fresh_node_id();
+        self.splits.insert(innovation, id);
+        id
+    }
+}
+
+// Genome that evolves its own topology. The network is kept acyclic so a single
+// pass in topological order evaluates it, same as `neural_net::forward_propagate`.
+#[derive(Debug, Clone)]
+pub struct Genome {
+    pub nodes: Vec<NodeGene>,
+    pub connections: Vec<ConnectionGene>,
+    input_count: usize,
+    output_count: usize,
+    // Node indices in evaluation order and incoming (node index, weight) pairs per node
+    evaluation_order: Vec<usize>,
+    incoming: Vec<Vec<(usize, f32)>>,
+}
+
+impl Genome {
+    // Inputs and bias fully connected to the outputs with random weights
+    pub fn new_minimal(input_count: usize, output_count: usize, tracker: &mut InnovationTracker) -> Genome {
+        let mut rng = rand::thread_rng();
+        let mut nodes = vec![];
+        for id in 0..input_count {
+            nodes.push(NodeGene { id, kind: NodeKind::Input });
+        }
+        nodes.push(NodeGene { id: input_count, kind: NodeKind::Bias });
+        for i in 0..output_count {
+            nodes.push(NodeGene { id: input_count + 1 + i, kind: NodeKind::Output });
+        }
+
+        let mut connections = vec![];
+        for from in 0..(input_count + 1) {
+            for i in 0..output_count {
+                let to = input_count + 1 + i;
+                connections.push(ConnectionGene {
+                    from,
+                    to,
+                    weight: rng.gen::<f32>() * 2.0 - 1.0,
+                    enabled: true,
+                    innovation: tracker.connection_innovation(from, to),
+                });
+            }
+        }
+
+        let mut genome = Genome {
+            nodes,
+            connections,
+            input_count,
+            output_count,
+            evaluation_order: vec![],
+            incoming: vec![],
+        };
+        genome.rebuild();
+        genome
+    }
+
+    fn node_index(&self, id: usize) -> Option<usize> {
+        self.nodes.iter().position(|node| node.id == id)
+    }
+
+    pub fn enabled_connection_count(&self) -> usize {
+        self.connections.iter().filter(|connection| connection.enabled).count()
+    }
+
+    pub fn hidden_node_count(&self) -> usize {
+        self.nodes.iter().filter(|node| node.kind == NodeKind::Hidden).count()
+    }
+
+    // Recomputes the evaluation order after the structure changed
+    fn rebuild(&mut self) {
+        let mut incoming: Vec<Vec<(usize, f32)>> = vec![vec![]; self.nodes.len()];
+        let mut outgoing: Vec<Vec<usize>> = vec![vec![]; self.nodes.len()];
+        for connection in self.connections.iter().filter(|connection| connection.enabled) {
+            if let (Some(from), Some(to)) = (self.node_index(connection.from), self.node_index(connection.to)) {
+                incoming[to].push((from, connection.weight));
+                outgoing[from].push(to);
+            }
+        }
+
+        // Kahn's algorithm
+        let mut remaining: Vec<usize> = incoming.iter().map(|inputs| inputs.len()).collect();
+        let mut queue: Vec<usize> = (0..self.nodes.len()).filter(|i| remaining[*i] == 0).collect();
+        let mut order = vec![];
+        while let Some(index) = queue.pop() {
+            order.push(index);
+            for &next in &outgoing[index] {
+                remaining[next] -= 1;
+                if remaining[next] == 0 {
+                    queue.push(next);
+                }
+            }
+        }
+
+        self.evaluation_order = order;
+        self.incoming = incoming;
+    }
+
+    pub fn activate(&self, inputs: &[f32]) -> Vec<f32> {
+        let mut values = vec![0.0; self.nodes.len()];
+        for &index in &self.evaluation_order {
+            values[index] = match self.nodes[index].kind {
+                NodeKind::Input => inputs.get(self.nodes[index].id).copied().unwrap_or(0.0),
+                NodeKind::Bias => 1.0,
+                NodeKind::Output | NodeKind::Hidden => {
+                    let sum: f32 = self.incoming[index].iter().map(|(from, weight)| values[*from] * weight).sum();
+                    sigmoid(sum)
+                }
+            };
+        }
+
+        (0..self.output_count)
+            .map(|i| self.node_index(self.input_count + 1 + i).map(|index| values[index]).unwrap_or(0.0))
+            .collect()
+    }
+
+    // True if there is a path from `from` to `to` along any connection
+    fn reaches(&self, from: usize, to: usize) -> bool {
+        let mut stack = vec![from];
+        let mut visited = vec![];
+        while let Some(id) = stack.pop() {
+            if id == to {
+                return true;
+            }
+            if visited.contains(&id) {
+                continue;
+            }
+            visited.push(id);
+            for connection in &self.connections {
+                if connection.from == id {
+                    stack.push(connection.to);
+                }
+            }
+        }
+        false
+    }
+
+    fn mutate_weights(&mut self, power: f32) {
+        let mut rng = rand::thread_rng();
+        for connection in &mut self.connections {
+            if rng.gen::<f32>() < 0.1 {
+                connection.weight = rng.gen::<f32>() * 2.0 - 1.0;
+            } else {
+                connection.weight += (rng.gen::<f32>() - 0.5) * power;
+            }
+        }
+    }
+
+    fn mutate_add_connection(&mut self, tracker: &mut InnovationTracker) {
+        let mut rng = rand::thread_rng();
+        // A few attempts at finding a pair that is not connected and would not create a cycle
+        for _ in 0..20 {
+            let from = &self.nodes[rng.gen_range(0..self.nodes.len())];
+            let to = &self.nodes[rng.gen_range(0..self.nodes.len())];
+            if to.kind == NodeKind::Input || to.kind == NodeKind::Bias || from.id == to.id {
+                continue;
+            }
+            let (from, to) = (from.id, to.id);
+            if self.connections.iter().any(|connection| connection.from == from && connection.to == to) {
+                continue;
+            }
+            if self.reaches(to, from) {
+                continue;
+            }
+            self.connections.push(ConnectionGene {
+                from,
+                to,
+                weight: rng.gen::<f32>() * 2.0 - 1.0,
+                enabled: true,
+                innovation: tracker.connection_innovation(from, to),
+            });
+            return;
+        }
+    }
+
+    fn mutate_add_node(&mut self, tracker: &mut InnovationTracker) {
+        let mut rng = rand::thread_rng();
+        let enabled: Vec<usize> = (0..self.connections.len()).filter(|i| self.connections[*i].enabled).collect();
+        if enabled.is_empty() {
+            return;
+        }
+        let split = enabled[rng.gen_range(0..enabled.len())];
+        self.connections[split].enabled = false;
+        let old = self.connections[split].clone();
+
+        let mut id = tracker.split_node_id(old.innovation);
+        if self.node_index(id).is_some() {
+            // This genome already split the connection once (it got re-enabled by crossover)
+            id = tracker.fresh_node_id();
+        }
+        self.nodes.push(NodeGene { id, kind: NodeKind::Hidden });
+
+        // Incoming weight of 1 and outgoing of the old weight keeps the behaviour close to the original
+        self.connections.push(ConnectionGene {
+            from: old.from,
+            to: id,
+            weight: 1.0,
+            enabled: true,
+            innovation: tracker.connection_innovation(old.from, id),
+        });
+        self.connections.push(ConnectionGene {
+            from: id,
+            to: old.to,
+            weight: old.weight,
+            enabled: true,
+            innovation: tracker.connection_innovation(id, old.to),
+        });
+    }
+
+    pub fn mutate(&mut self, tracker: &mut InnovationTracker, config: &NeatConfig) {
+        let mut rng = rand::thread_rng();
+        if rng.gen::<f32>() < config.weight_mutation_probability {
+            self.mutate_weights(config.weight_mutation_power);
+        }
+        if rng.gen::<f32>() < config.add_connection_probability {
+            self.mutate_add_connection(tracker);
+        }
+        if rng.gen::<f32>() < config.add_node_probability {
+            self.mutate_add_node(tracker);
+        }
+        self.rebuild();
+    }
+
+    // Matching genes are picked randomly, disjoint and excess genes come from the fitter parent.
+    // The child has the structure of the fitter parent, so it stays acyclic.
+    pub fn crossover(fitter: &Genome, other: &Genome) -> Genome {
+        let mut rng = rand::thread_rng();
+        let mut child = fitter.clone();
+        for connection in &mut child.connections {
+            if let Some(matching) = other.connections.iter().find(|c| c.innovation == connection.innovation) {
+                if rng.gen::<f32>() < 0.5 {
+                    connection.weight = matching.weight;
+                }
+                if !connection.enabled || !matching.enabled {
+                    connection.enabled = rng.gen::<f32>() > 0.75;
+                }
+            }
+        }
+        child.rebuild();
+        child
+    }
+
+    pub fn compatibility_distance(&self, other: &Genome, config: &NeatConfig) -> f32 {
+        let mut own: Vec<&ConnectionGene> = self.connections.iter().collect();
+        let mut others: Vec<&ConnectionGene> = other.connections.iter().collect();
+        own.sort_by_key(|connection| connection.innovation);
+        others.sort_by_key(|connection| connection.innovation);
+
+        let own_max = own.last().map(|c| c.innovation).unwrap_or(0);
+        let other_max = others.last().map(|c| c.innovation).unwrap_or(0);
+
+        let mut excess = 0;
+        let mut disjoint = 0;
+        let mut weight_difference = 0.0;
+        let mut matching = 0;
+        let (mut i, mut j) = (0, 0);
+        while i < own.len() || j < others.len() {
+            if i < own.len() && j < others.len() && own[i].innovation == others[j].innovation {
+                weight_difference += (own[i].weight - others[j].weight).abs();
+                matching += 1;
+                i += 1;
+                j += 1;
+            } else if j >= others.len() || (i < own.len() && own[i].innovation < others[j].innovation) {
+                if own[i].innovation > other_max {
+                    excess += 1;
+                } else {
+                    disjoint += 1;
+                }
+                i += 1;
+            } else {
+                if others[j].innovation > own_max {
+                    excess += 1;
+                } else {
+                    disjoint += 1;
+                }
+                j += 1;
+            }
+        }
+
+        let n = own.len().max(others.len()).max(1) as f32;
+        let average_weight_difference = if matching > 0 { weight_difference / matching as f32 } else { 0.0 };
+        config.excess_coefficient * excess as f32 / n
+            + config.disjoint_coefficient * disjoint as f32 / n
+            + config.weight_coefficient * average_weight_difference
+    }
+}
+
+pub struct NeatConfig {
+    pub excess_coefficient: f32,
+    pub disjoint_coefficient: f32,
+    pub weight_coefficient: f32,
+    pub compatibility_threshold: f32,
+    pub target_species: usize,
+    pub max_staleness: u32,
+    pub survival_fraction: f32,
+    pub weight_mutation_probability: f32,
+    pub weight_mutation_power: f32,
+    pub add_connection_probability: f32,
+    pub add_node_probability: f32,
+}
+
+impl Default for NeatConfig {
+    fn default() -> NeatConfig {
+        NeatConfig {
+            excess_coefficient: 1.0,
+            disjoint_coefficient: 1.0,
+            weight_coefficient: 0.4,
+            compatibility_threshold: 3.0,
+            target_species: 10,
+            max_staleness: 15,
+            survival_fraction: 0.5,
+            weight_mutation_probability: 0.8,
+            weight_mutation_power: 0.5,
+            add_connection_probability: 0.05,
+            add_node_probability: 0.03,
+        }
+    }
+}
+
+struct Species {
+    representative: Genome,
+    members: Vec<usize>,
+    best_score: f32,
+    staleness: u32,
+}
+
+pub struct NeatPopulation {
+    pub config: NeatConfig,
+    tracker: InnovationTracker,
+    species: Vec<Species>,
+    input_count: usize,
+    output_count: usize,
+}
+
+impl NeatPopulation {
+    pub fn new(input_count: usize, output_count: usize, config: NeatConfig) -> NeatPopulation {
+        NeatPopulation {
+            config,
+            tracker: InnovationTracker::new(input_count, output_count),
+            species: vec![],
+            input_count,
+            output_count,
+        }
+    }
+
+    pub fn new_genome(&mut self) -> Genome {
+        Genome::new_minimal(self.input_count, self.output_count, &mut self.tracker)
+    }
+
+    pub fn species_count(&self) -> usize {
+        self.species.len()
+    }
+
+    fn speciate(&mut self, population: &[(Genome, f32)]) {
+        for species in &mut self.species {
+            species.members.clear();
+        }
+        for (index, (genome, _)) in population.iter().enumerate() {
+            let found = self.species.iter().position(|species| {
+                genome.compatibility_distance(&species.representative, &self.config) < self.config.compatibility_threshold
+            });
+            match found {
+                Some(species_index) => self.species[species_index].members.push(index),
+                None => self.species.push(Species {
+                    representative: genome.clone(),
+                    members: vec![index],
+                    best_score: f32::MAX,
+                    staleness: 0,
+                }),
+            }
+        }
+        self.species.retain(|species| !species.members.is_empty());
+
+        // Nudge the threshold towards the target number of species
+        if self.species.len() < self.config.target_species {
+            self.config.compatibility_threshold = (self.config.compatibility_threshold - 0.1).max(0.3);
+        } else if self.species.len() > self.config.target_species {
+            self.config.compatibility_threshold += 0.1;
+        }
+    }
+
+    // Scores are minimized like `Ship::score`. Returns the genomes of the next generation.
+    pub fn next_generation(&mut self, population: Vec<(Genome, f32)>) -> Vec<Genome> {
+        let mut rng = rand::thread_rng();
+        let population_size = population.len();
+        self.speciate(&population);
+
+        // Track improvement per species and drop the ones that stagnated (but never the best one)
+        let best_overall = population.iter().map(|(_, score)| *score).fold(f32::MAX, f32::min);
+        for species in &mut self.species {
+            let best = species.members.iter().map(|i| population[*i].1).fold(f32::MAX, f32::min);
+            if best < species.best_score {
+                species.best_score = best;
+                species.staleness = 0;
+            } else {
+                species.staleness += 1;
+            }
+        }
+        let max_staleness = self.config.max_staleness;
+        self.species.retain(|species| {
+            species.staleness < max_staleness || species.members.iter().any(|i| population[*i].1 == best_overall)
+        });
+
+        // Explicit fitness sharing on a fitness where higher is better
+        let worst = population.iter().map(|(_, score)| *score).fold(f32::MIN, f32::max);
+        let shared_fitness: Vec<f32> = self.species.iter().map(|species| {
+            species.members.iter().map(|i| worst - population[*i].1 + 1e-3).sum::<f32>() / species.members.len() as f32
+        }).collect();
+        let total_fitness: f32 = shared_fitness.iter().sum();
+
+        let mut offspring_counts: Vec<usize> = shared_fitness.iter()
+            .map(|fitness| (fitness / total_fitness * population_size as f32) as usize)
+            .collect();
+        // Rounding leftovers go to the best species
+        let assigned: usize = offspring_counts.iter().sum();
+        if let Some(best_species) = (0..self.species.len()).max_by(|a, b| shared_fitness[*a].partial_cmp(&shared_fitness[*b]).unwrap()) {
+            offspring_counts[best_species] += population_size.saturating_sub(assigned);
+        }
+
+        let mut next = vec![];
+        for (species_index, species) in self.species.iter_mut().enumerate() {
+            let count = offspring_counts[species_index];
+            let mut members = species.members.clone();
+            members.sort_by(|a, b| population[*a].1.partial_cmp(&population[*b].1).unwrap());
+            let survivors = &members[..((members.len() as f32 * self.config.survival_fraction).ceil() as usize).max(1)];
+
+            for child_index in 0..count {
+                // Species champion is copied unchanged
+                if child_index == 0 {
+                    next.push(population[members[0]].0.clone());
+                    continue;
+                }
+                let first = survivors[rng.gen_range(0..survivors.len())];
+                let mut child = if rng.gen::<f32>() < 0.75 && survivors.len() > 1 {
+                    let second = survivors[rng.gen_range(0..survivors.len())];
+                    if population[first].1 <= population[second].1 {
+                        Genome::crossover(&population[first].0, &population[second].0)
+                    } else {
+                        Genome::crossover(&population[second].0, &population[first].0)
+                    }
+                } else {
+                    population[first].0.clone()
+                };
+                child.mutate(&mut self.tracker, &self.config);
+                next.push(child);
+            }
+
+            species.representative = population[members[rng.gen_range(0..members.len())]].0.clone();
+        }
+        next.truncate(population_size);
+        next
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn connection(from: usize, to: usize, weight: f32, enabled: bool, innovation: usize) -> ConnectionGene {
+        ConnectionGene { from, to, weight, enabled, innovation }
+    }
+
+    #[test]
+    fn structural_mutations_keep_the_genome_acyclic() {
+        let mut tracker = InnovationTracker::new(3, 2);
+        let mut genome = Genome::new_minimal(3, 2, &mut tracker);
+        for _ in 0..200 {
+            genome.mutate_add_node(&mut tracker);
+            genome.mutate_add_connection(&mut tracker);
+            genome.mutate_add_connection(&mut tracker);
+            genome.rebuild();
+        }
+        assert!(genome.hidden_node_count() > 0);
+        // Disabled connections count too, crossover can enable them again
+        for connection in &genome.connections {
+            assert!(!genome.reaches(connection.to, connection.from), "cycle through {} -> {}", connection.from, connection.to);
+        }
+        assert_eq!(genome.evaluation_order.len(), genome.nodes.len());
+    }
+
+    #[test]
+    fn activation_follows_the_enabled_connections() {
+        // Inputs 0 and 1, bias 2, output 3 and hidden 4
+        let mut genome = Genome {
+            nodes: vec![
+                NodeGene { id: 0, kind: NodeKind::Input },
+                NodeGene { id: 1, kind: NodeKind::Input },
+                NodeGene { id: 2, kind: NodeKind::Bias },
+                NodeGene { id: 3, kind: NodeKind::Output },
+                NodeGene { id: 4, kind: NodeKind::Hidden },
+            ],
+            connections: vec![
+                connection(0, 4, 1.0, true, 0),
+                connection(1, 4, -2.0, true, 1),
+                connection(2, 3, 0.5, true, 2),
+                connection(4, 3, 3.0, true, 3),
+                connection(0, 3, 100.0, false, 4),
+            ],
+            input_count: 2,
+            output_count: 1,
+            evaluation_order: vec![],
+            incoming: vec![],
+        };
+        genome.rebuild();
+
+        let (x0, x1) = (0.5, 0.75);
+        let hidden = sigmoid(x0 - 2.0 * x1);
+        let output = genome.activate(&[x0, x1]);
+        assert_eq!(output.len(), 1);
+        assert!((output[0] - sigmoid(0.5 + 3.0 * hidden)).abs() < 1e-6, "{:?}", output);
+    }
+}
@@ -0,0 +1,73 @@
+use crate::random;
+
+// OpenAI style natural evolution strategy with mirrored sampling and
+// rank based fitness shaping. Scores are minimized.
+pub struct Nes {
+    pub mean: Vec<f32>,
+    pub sigma: f32,
+    pub learning_rate: f32,
+    population_size: usize,
+}
+
+impl Nes {
+    pub fn new(initial_mean: &[f32], sigma: f32, learning_rate: f32, population_size: usize) -> Nes {
+        Nes {
+            mean: initial_mean.to_vec(),
+            sigma,
+            learning_rate,
+            // Mirrored sampling needs pairs
+            population_size: population_size / 2 * 2,
+        }
+    }
+
+    pub fn ask(&self) -> Vec<Vec<f32>> {
+        let mut rng = rand::thread_rng();
+        let mut samples = vec![];
+        for _ in 0..(self.population_size / 2) {
+            let noise: Vec<f32> = self.mean.iter().map(|_| random::normal(&mut rng)).collect();
+            samples.push(self.mean.iter().zip(&noise).map(|(m, e)| m + self.sigma * e).collect());
+            samples.push(self.mean.iter().zip(&noise).map(|(m, e)| m - self.sigma * e).collect());
+        }
+        samples
+    }
+
+    pub fn tell(&mut self, samples: &[Vec<f32>], scores: &[f32]) {
+        let mut order: Vec<usize> = (0..samples.len()).collect();
+        order.sort_by(|a, b| scores[*a].partial_cmp(&scores[*b]).unwrap());
+
+        // Best sample gets utility 0.5, worst -0.5
+        let mut utilities = vec![0.0; samples.len()];
+        for (rank, &index) in order.iter().enumerate() {
+            utilities[index] = 0.5 - rank as f32 / (samples.len() - 1).max(1) as f32;
+        }
+
+        let mut gradient = vec![0.0; self.mean.len()];
+        for (sample, utility) in samples.iter().zip(&utilities) {
+            for i in 0..self.mean.len() {
+                gradient[i] += utility * (sample[i] - self.mean[i]) / self.sigma;
+            }
+        }
+        let scale = self.learning_rate / (samples.len() as f32 * self.sigma);
+        for (m, g) in self.mean.iter_mut().zip(&gradient) {
+            *m += scale * g;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn minimizes_a_sphere() {
+        let sphere = |x: &[f32]| x.iter().map(|x| x * x).sum::<f32>();
+        let mut strategy = Nes::new(&[3.0, -2.0, 1.0, 4.0, -1.0], 0.1, 0.05, 40);
+        for _ in 0..300 {
+            let samples = strategy.ask();
+            let scores: Vec<f32> = samples.iter().map(|sample| sphere(sample)).collect();
+            strategy.tell(&samples, &scores);
+        }
+        // Sigma is fixed, so the mean keeps jittering around the minimum instead of closing in on it
+        assert!(sphere(&strategy.mean) < 1e-2, "mean {:?}", strategy.mean);
+    }
+}
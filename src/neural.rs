@@ -1,5 +1,6 @@
 
 use rand::Rng;
+use crate::random;
 
 #[derive(Debug, Clone)]
 pub struct neural_net {
@@ -8,35 +9,175 @@ pub struct neural_net {
     layer_sizes: Vec<u32>,
 }
 
+// Version of the text written by `to_text`. Text without a format line is from before the
+// bias fix and gets migrated when it is read.
+const TEXT_FORMAT: u32 = 2;
+
 fn sigmoid(x: f32) -> f32 {
     1.0 / (1.0 + (-x).exp())
 }
 
-impl neural_net {
-    pub fn new(layer_sizes: Vec<u32>) -> neural_net {
-        let mut rng = rand::thread_rng();
+// How the weights of a fresh network are drawn. Fan in and fan out are the cell counts of
+// the two layers a weight connects, biases start at 0 except with `Uniform`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Initializer {
+    // Every weight and bias uniform in [-1, 1]
+    #[default]
+    Uniform,
+    // Glorot: uniform in +-sqrt(6 / (fan in + fan out))
+    Xavier,
+    // Gaussian with deviation sqrt(2 / fan in)
+    He,
+    // Orthonormal rows (or columns when there are more outputs than inputs)
+    Orthogonal,
+    Zeros,
+    // Gaussian with deviation 0.1
+    SmallNormal,
+}
 
-        let mut weights: Vec<Vec<f32>> = Vec::new();
-        let mut layer_values: Vec<Vec<f32>> = Vec::new();
+impl Initializer {
+    pub fn named(name: &str) -> Option<Initializer> {
+        match name {
+            "uniform" => Some(Initializer::Uniform),
+            "xavier" => Some(Initializer::Xavier),
+            "he" => Some(Initializer::He),
+            "orthogonal" => Some(Initializer::Orthogonal),
+            "zeros" => Some(Initializer::Zeros),
+            "small-normal" => Some(Initializer::SmallNormal),
+            _ => None,
+        }
+    }
 
-        for i in 0..layer_sizes.len() {
-            let mut layer = Vec::new();
-            for _j in 0..layer_sizes[i] {
-                layer.push(0.0);
+    // Weights between two layers, laid out like `neural_net::weights`: one row per output
+    // cell with its input weights followed by its bias
+    fn layer<R: Rng>(&self, rng: &mut R, from_size: usize, to_size: usize) -> Vec<f32> {
+        let fan_in = from_size as f32;
+        let fan_out = to_size as f32;
+        let input_weights: Vec<Vec<f32>> = match self {
+            Initializer::Uniform => {
+                return (0..(from_size + 1) * to_size).map(|_| rng.gen::<f32>() * 2.0 - 1.0).collect();
+            },
+            Initializer::Xavier => {
+                let limit = (6. / (fan_in + fan_out).max(1.)).sqrt();
+                random_matrix(to_size, from_size, || (rng.gen::<f32>() * 2. - 1.) * limit)
+            },
+            Initializer::He => {
+                let deviation = (2. / fan_in.max(1.)).sqrt();
+                random_matrix(to_size, from_size, || random::normal(rng) * deviation)
+            },
+            Initializer::Orthogonal => orthogonal_matrix(rng, to_size, from_size),
+            Initializer::Zeros => random_matrix(to_size, from_size, || 0.),
+            Initializer::SmallNormal => random_matrix(to_size, from_size, || random::normal(rng) * 0.1),
+        };
+        input_weights.into_iter().flat_map(|row| row.into_iter().chain([0.])).collect()
+    }
+}
+
+fn random_matrix<F: FnMut() -> f32>(rows: usize, columns: usize, mut sample: F) -> Vec<Vec<f32>> {
+    (0..rows).map(|_| (0..columns).map(|_| sample()).collect()).collect()
+}
+
+// Gram-Schmidt on gaussian vectors. The shorter side gets orthonormal vectors, so either
+// the rows or the columns are orthonormal.
+fn orthogonal_matrix<R: Rng>(rng: &mut R, rows: usize, columns: usize) -> Vec<Vec<f32>> {
+    let (count, length) = (rows.min(columns), rows.max(columns));
+    let mut vectors: Vec<Vec<f32>> = Vec::new();
+    while vectors.len() < count {
+        let mut vector: Vec<f32> = (0..length).map(|_| random::normal(rng)).collect();
+        for other in &vectors {
+            let projection: f32 = vector.iter().zip(other).map(|(a, b)| a * b).sum();
+            for (value, other_value) in vector.iter_mut().zip(other) {
+                *value -= projection * other_value;
             }
-            layer_values.push(layer);
         }
-        for i in 0..layer_sizes.len() - 1 {
-            let mut layer: Vec<f32> = Vec::new();
+        let norm = vector.iter().map(|value| value * value).sum::<f32>().sqrt();
+        // Nearly parallel to the earlier vectors, draw again
+        if norm < 1e-3 {
+            continue;
+        }
+        vectors.push(vector.iter().map(|value| value / norm).collect());
+    }
+    if rows <= columns {
+        vectors
+    } else {
+        (0..rows).map(|row| vectors.iter().map(|column| column[row]).collect()).collect()
+    }
+}
 
-            // Add +1 to layer_sizes[i] to account for bias
-            let weight_layer_size = (layer_sizes[i] + 1) * layer_sizes[i + 1];
+#[derive(Debug, Clone)]
+pub enum Optimizer {
+    Sgd { learning_rate: f32 },
+    Adam { learning_rate: f32, beta1: f32, beta2: f32 },
+}
 
-            for _j in 0..weight_layer_size {
-                layer.push(rng.gen::<f32>() * 2.0 - 1.0);
-            }
-            weights.push(layer);
+impl Optimizer {
+    pub fn named(name: &str) -> Option<Optimizer> {
+        match name {
+            "sgd" => Some(Optimizer::Sgd { learning_rate: 0.5 }),
+            "adam" => Some(Optimizer::adam(0.01)),
+            _ => None,
+        }
+    }
+
+    pub fn adam(learning_rate: f32) -> Optimizer {
+        Optimizer::Adam { learning_rate, beta1: 0.9, beta2: 0.999 }
+    }
+}
+
+// Applies gradients to a flat list of parameters, keeping the Adam moments between steps
+#[derive(Debug, Clone)]
+pub struct GradientDescent {
+    optimizer: Optimizer,
+    first_moment: Vec<f32>,
+    second_moment: Vec<f32>,
+    steps: i32,
+}
+
+impl GradientDescent {
+    pub fn new(optimizer: Optimizer, parameter_count: usize) -> GradientDescent {
+        GradientDescent {
+            optimizer,
+            first_moment: vec![0.; parameter_count],
+            second_moment: vec![0.; parameter_count],
+            steps: 0,
+        }
+    }
+
+    pub fn step(&mut self, parameters: &mut [f32], gradient: &[f32]) {
+        self.steps += 1;
+        match self.optimizer {
+            Optimizer::Sgd { learning_rate } => {
+                for (parameter, value) in parameters.iter_mut().zip(gradient) {
+                    *parameter -= learning_rate * value;
+                }
+            },
+            Optimizer::Adam { learning_rate, beta1, beta2 } => {
+                let first_correction = 1. - beta1.powi(self.steps);
+                let second_correction = 1. - beta2.powi(self.steps);
+                for (i, value) in gradient.iter().enumerate() {
+                    self.first_moment[i] = beta1 * self.first_moment[i] + (1. - beta1) * value;
+                    self.second_moment[i] = beta2 * self.second_moment[i] + (1. - beta2) * value * value;
+                    let step = (self.first_moment[i] / first_correction) / ((self.second_moment[i] / second_correction).sqrt() + 1e-8);
+                    parameters[i] -= learning_rate * step;
+                }
+            },
         }
+    }
+}
+
+impl neural_net {
+    pub fn new(layer_sizes: Vec<u32>) -> neural_net {
+        neural_net::with_initializer(layer_sizes, Initializer::Uniform)
+    }
+
+    pub fn with_initializer(layer_sizes: Vec<u32>, initializer: Initializer) -> neural_net {
+        let mut rng = rand::thread_rng();
+
+        let layer_values: Vec<Vec<f32>> = layer_sizes.iter().map(|size| vec![0.0; *size as usize]).collect();
+        // Every layer has one weight per input cell and a bias for each of its cells
+        let weights: Vec<Vec<f32>> = layer_sizes.windows(2)
+            .map(|pair| initializer.layer(&mut rng, pair[0] as usize, pair[1] as usize))
+            .collect();
         neural_net {
             weights: weights,
             layer_values: layer_values,
@@ -44,6 +185,17 @@ impl neural_net {
         }
     }
 
+    // Copy with gaussian noise of the given deviation added to every weight, used to seed a
+    // population from a saved champion
+    pub fn with_noise(&self, deviation: f32) -> neural_net {
+        let mut rng = rand::thread_rng();
+        let mut new_net = self.clone();
+        for weight in new_net.weights.iter_mut().flatten() {
+            *weight += random::normal(&mut rng) * deviation;
+        }
+        new_net
+    }
+
     pub fn mix_randomly_with_other(&self, other: &neural_net) -> neural_net {
         // Returns new neural_net that is a mix of self and other
         let mut rng = rand::thread_rng();
@@ -80,6 +232,99 @@ impl neural_net {
         new_net
     }
 
+    pub fn input_size(&self) -> usize {
+        self.layer_sizes[0] as usize
+    }
+
+    pub fn output_size(&self) -> usize {
+        self.layer_sizes[self.layer_sizes.len() - 1] as usize
+    }
+
+    pub fn weight_count(&self) -> usize {
+        self.weights.iter().map(|layer| layer.len()).sum()
+    }
+
+    // Weights between each pair of layers, laid out like `weights`
+    pub fn weight_layers(&self) -> &[Vec<f32>] {
+        &self.weights
+    }
+
+    // All weights in one vector, layer by layer
+    pub fn get_weights_flat(&self) -> Vec<f32> {
+        self.weights.iter().flatten().copied().collect()
+    }
+
+    pub fn set_weights_flat(&mut self, values: &[f32]) {
+        let mut index = 0;
+        for layer in &mut self.weights {
+            for weight in layer.iter_mut() {
+                *weight = values[index];
+                index += 1;
+            }
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        // A format line, the layer sizes, then one line of weights per layer
+        let mut lines = vec![
+            format!("neural_net {}", TEXT_FORMAT),
+            self.layer_sizes.iter().map(|size| size.to_string()).collect::<Vec<String>>().join(" "),
+        ];
+        for layer in &self.weights {
+            lines.push(layer.iter().map(|weight| weight.to_string()).collect::<Vec<String>>().join(" "));
+        }
+        lines.join("\n")
+    }
+
+    // Also reads networks saved before the format line existed, see `migrate_legacy_bias`
+    pub fn from_text(text: &str) -> Option<neural_net> {
+        let mut lines = text.lines().peekable();
+        let legacy = !lines.peek()?.starts_with("neural_net ");
+        if !legacy {
+            let format: u32 = lines.next()?["neural_net ".len()..].trim().parse().ok()?;
+            if format != TEXT_FORMAT {
+                return None;
+            }
+        }
+        let layer_sizes: Vec<u32> = lines.next()?
+            .split_whitespace()
+            .map(|value| value.parse().ok())
+            .collect::<Option<Vec<u32>>>()?;
+        if layer_sizes.len() < 2 {
+            return None;
+        }
+
+        let mut net = neural_net::new(layer_sizes);
+        for i in 0..net.weights.len() {
+            let layer: Vec<f32> = lines.next()?
+                .split_whitespace()
+                .map(|value| value.parse().ok())
+                .collect::<Option<Vec<f32>>>()?;
+            if layer.len() != net.weights[i].len() {
+                return None;
+            }
+            net.weights[i] = layer;
+        }
+        if legacy {
+            net.migrate_legacy_bias();
+        }
+        Some(net)
+    }
+
+    // Networks from before the bias fix used the last input weight as the bias as well and
+    // never read the bias slot. Copying that weight into the bias slot gives the same outputs.
+    fn migrate_legacy_bias(&mut self) {
+        for (from_layer_index, layer) in self.weights.iter_mut().enumerate() {
+            let from_size = self.layer_sizes[from_layer_index] as usize;
+            if from_size == 0 {
+                continue;
+            }
+            for cell_weights in layer.chunks_mut(from_size + 1) {
+                cell_weights[from_size] = cell_weights[from_size - 1];
+            }
+        }
+    }
+
     pub fn set_first_layer(&mut self, values: Vec<f32>) {
         for i in 0..values.len() {
             self.layer_values[0][i] = values[i];
@@ -90,6 +335,54 @@ impl neural_net {
         self.layer_values[self.layer_values.len() - 1].clone()
     }
 
+    // Outputs for the given inputs, the cell values are kept for `backpropagate`
+    pub fn outputs(&mut self, inputs: &[f32]) -> Vec<f32> {
+        self.set_first_layer(inputs.to_vec());
+        self.forward_propagate();
+        self.get_last_layer()
+    }
+
+    // Gradient of a loss for every weight, laid out like `get_weights_flat`, given the
+    // derivative of the loss by each output of the last forward pass
+    pub fn backpropagate(&self, output_gradient: &[f32]) -> Vec<f32> {
+        // Derivative of the loss by the sum going into each cell, sigmoid' = y (1 - y)
+        let outputs = &self.layer_values[self.layer_values.len() - 1];
+        let mut deltas: Vec<f32> = output_gradient.iter().zip(outputs)
+            .map(|(gradient, output)| gradient * output * (1. - output))
+            .collect();
+
+        let mut gradients: Vec<Vec<f32>> = vec![vec![]; self.weights.len()];
+        for from_layer_index in (0..self.weights.len()).rev() {
+            let from_values = &self.layer_values[from_layer_index];
+            let from_size = from_values.len();
+            let mut gradient = vec![0.; self.weights[from_layer_index].len()];
+            let mut from_deltas = vec![0.; from_size];
+            for (to_cell_index, delta) in deltas.iter().enumerate() {
+                let row = (from_size + 1) * to_cell_index;
+                for (from_cell_index, value) in from_values.iter().enumerate() {
+                    gradient[row + from_cell_index] = delta * value;
+                    from_deltas[from_cell_index] += delta * self.weights[from_layer_index][row + from_cell_index];
+                }
+                gradient[row + from_size] = *delta;
+            }
+            gradients[from_layer_index] = gradient;
+            // Every layer after the inputs went through the sigmoid
+            deltas = from_deltas.iter().zip(from_values).map(|(delta, value)| delta * value * (1. - value)).collect();
+        }
+        gradients.into_iter().flatten().collect()
+    }
+
+    // Mean squared error of the outputs for one sample and its gradient for every weight
+    pub fn mse_gradient(&mut self, inputs: &[f32], targets: &[f32]) -> (f32, Vec<f32>) {
+        let outputs = self.outputs(inputs);
+        let count = outputs.len() as f32;
+        let loss = outputs.iter().zip(targets).map(|(output, target)| (output - target).powi(2)).sum::<f32>() / count;
+        let output_gradient: Vec<f32> = outputs.iter().zip(targets)
+            .map(|(output, target)| 2. * (output - target) / count)
+            .collect();
+        (loss, self.backpropagate(&output_gradient))
+    }
+
     pub fn forward_propagate(&mut self) {
         for to_layer_index in 1..self.layer_sizes.len() {
             let from_layer_index = to_layer_index - 1;
@@ -106,8 +399,8 @@ impl neural_net {
                     let weight = weight_array[((from_size + 1) * to_cell_index + from_cell_index) as usize];
                     sum += from_input_value * weight;
                 }
-                // Bias
-                let weight = weight_array[((from_size + 1) * to_cell_index + from_size - 1) as usize];
+                // Bias, the slot after the input weights
+                let weight = weight_array[((from_size + 1) * to_cell_index + from_size) as usize];
                 sum += weight;
 
                 self.layer_values[to_layer_index as usize][to_cell_index as usize] = sigmoid(sum);
@@ -115,3 +408,162 @@ impl neural_net {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn net_with_weights(layer_sizes: Vec<u32>, weights: &[f32]) -> neural_net {
+        let mut net = neural_net::new(layer_sizes);
+        assert_eq!(net.weight_count(), weights.len());
+        net.set_weights_flat(weights);
+        net
+    }
+
+    fn run(net: &mut neural_net, inputs: Vec<f32>) -> Vec<f32> {
+        net.set_first_layer(inputs);
+        net.forward_propagate();
+        net.get_last_layer()
+    }
+
+    fn assert_close(actual: &[f32], expected: &[f32]) {
+        assert_eq!(actual.len(), expected.len());
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-6, "{:?} instead of {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn single_cell_uses_every_input_weight_once_and_the_bias_slot() {
+        // 0.5 * 1 - 1 * 2 + 0.25
+        let mut net = net_with_weights(vec![2, 1], &[0.5, -1., 0.25]);
+        assert_close(&run(&mut net, vec![1., 2.]), &[sigmoid(-1.25)]);
+    }
+
+    #[test]
+    fn bias_alone_sets_the_output_when_input_weights_are_zero() {
+        let mut net = net_with_weights(vec![3, 2], &[0., 0., 0., 2., 0., 0., 0., -1.]);
+        assert_close(&run(&mut net, vec![5., -3., 7.]), &[sigmoid(2.), sigmoid(-1.)]);
+    }
+
+    #[test]
+    fn weights_are_laid_out_per_output_cell() {
+        // Cell 0 reads only the second input, cell 1 only the first
+        let mut net = net_with_weights(vec![2, 2], &[0., 1., 0., 3., 0., 0.]);
+        assert_close(&run(&mut net, vec![0.5, -0.5]), &[sigmoid(-0.5), sigmoid(1.5)]);
+    }
+
+    #[test]
+    fn two_layers() {
+        // Hidden: sigmoid(2x - 1) and sigmoid(-x + 0.5), output: sigmoid(h0 - h1 + 0.1)
+        let mut net = net_with_weights(vec![1, 2, 1], &[2., -1., -1., 0.5, 1., -1., 0.1]);
+        let x = 0.75;
+        let h0 = sigmoid(2. * x - 1.);
+        let h1 = sigmoid(-x + 0.5);
+        assert_close(&run(&mut net, vec![x]), &[sigmoid(h0 - h1 + 0.1)]);
+    }
+
+    #[test]
+    fn legacy_text_keeps_its_old_outputs() {
+        // Saved before the fix: the last input weight 0.7 also acted as the bias and the bias
+        // slot 9 was never read, so the old output was sigmoid(0.5 * 1 + 0.7 * 2 + 0.7)
+        let legacy = "2 1\n0.5 0.7 9";
+        let mut net = neural_net::from_text(legacy).unwrap();
+        assert_close(&run(&mut net, vec![1., 2.]), &[sigmoid(2.6)]);
+
+        // Saving writes the current format, which is read back without migrating again
+        let mut reloaded = neural_net::from_text(&net.to_text()).unwrap();
+        assert!(net.to_text().starts_with("neural_net 2\n"));
+        assert_eq!(reloaded.get_weights_flat(), vec![0.5, 0.7, 0.7]);
+        assert_close(&run(&mut reloaded, vec![1., 2.]), &[sigmoid(2.6)]);
+    }
+
+    #[test]
+    fn text_round_trip_keeps_the_bias() {
+        let net = net_with_weights(vec![2, 1], &[0.5, -1., 0.25]);
+        let reloaded = neural_net::from_text(&net.to_text()).unwrap();
+        assert_eq!(reloaded.get_weights_flat(), net.get_weights_flat());
+    }
+
+    #[test]
+    fn unknown_format_is_rejected() {
+        assert!(neural_net::from_text("neural_net 9\n2 1\n0.5 0.7 9").is_none());
+    }
+
+    // Input weights of one layer as rows per output cell, without the biases
+    fn input_rows(net: &neural_net, layer: usize) -> Vec<Vec<f32>> {
+        let from_size = net.layer_sizes[layer] as usize;
+        net.weights[layer].chunks(from_size + 1).map(|row| row[..from_size].to_vec()).collect()
+    }
+
+    fn biases(net: &neural_net, layer: usize) -> Vec<f32> {
+        let from_size = net.layer_sizes[layer] as usize;
+        net.weights[layer].chunks(from_size + 1).map(|row| row[from_size]).collect()
+    }
+
+    #[test]
+    fn zeros_give_half_on_every_output() {
+        let mut net = neural_net::with_initializer(vec![3, 4, 2], Initializer::Zeros);
+        assert_close(&run(&mut net, vec![1., -2., 3.]), &[0.5, 0.5]);
+    }
+
+    #[test]
+    fn xavier_and_he_scale_with_the_layer_size() {
+        let net = neural_net::with_initializer(vec![20, 10, 2], Initializer::Xavier);
+        let limit = (6f32 / 30.).sqrt();
+        assert!(input_rows(&net, 0).iter().flatten().all(|weight| weight.abs() <= limit));
+        assert!(biases(&net, 0).iter().chain(&biases(&net, 1)).all(|bias| *bias == 0.));
+
+        let net = neural_net::with_initializer(vec![400, 50], Initializer::He);
+        let weights: Vec<f32> = input_rows(&net, 0).into_iter().flatten().collect();
+        let variance = weights.iter().map(|weight| weight * weight).sum::<f32>() / weights.len() as f32;
+        assert!((variance - 2. / 400.).abs() < 0.001, "variance {}", variance);
+    }
+
+    #[test]
+    fn orthogonal_rows_or_columns_are_orthonormal() {
+        for (from_size, to_size) in [(5, 3), (3, 5), (4, 4)] {
+            let net = neural_net::with_initializer(vec![from_size, to_size], Initializer::Orthogonal);
+            let rows = input_rows(&net, 0);
+            let columns: Vec<Vec<f32>> = (0..from_size as usize).map(|i| rows.iter().map(|row| row[i]).collect()).collect();
+            let vectors = if to_size <= from_size { rows } else { columns };
+            for (i, a) in vectors.iter().enumerate() {
+                for (j, b) in vectors.iter().enumerate() {
+                    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+                    let expected = if i == j { 1. } else { 0. };
+                    assert!((dot - expected).abs() < 1e-4, "{}x{}: {} . {} = {}", from_size, to_size, i, j, dot);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn noise_moves_every_weight_a_little() {
+        let net = neural_net::new(vec![4, 3, 2]);
+        let noisy = net.with_noise(0.01);
+        let pairs: Vec<(f32, f32)> = net.get_weights_flat().into_iter().zip(noisy.get_weights_flat()).collect();
+        assert!(pairs.iter().all(|(a, b)| (a - b).abs() < 0.1));
+        assert!(pairs.iter().any(|(a, b)| a != b));
+    }
+
+    #[test]
+    fn mse_gradient_matches_finite_differences() {
+        let mut net = neural_net::new(vec![3, 4, 2]);
+        let inputs = [0.3, -1.2, 0.8];
+        let targets = [0.9, 0.1];
+        let (_, gradient) = net.mse_gradient(&inputs, &targets);
+
+        let weights = net.get_weights_flat();
+        let step = 1e-2;
+        for (i, analytic) in gradient.iter().enumerate() {
+            let mut loss_at = |offset: f32| {
+                let mut shifted = weights.clone();
+                shifted[i] += offset;
+                net.set_weights_flat(&shifted);
+                net.mse_gradient(&inputs, &targets).0
+            };
+            let numeric = (loss_at(step) - loss_at(-step)) / (2. * step);
+            assert!((numeric - analytic).abs() < 1e-3, "weight {}: {} instead of {}", i, analytic, numeric);
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use rand::Rng;
+
+// Accumulates what a ship did during an episode, independent of how well it did it
+#[derive(Debug, Clone, Default)]
+pub struct Behaviour {
+    pub final_x: f32,
+    pub final_y: f32,
+    tilt_sum: f32,
+    throttle_sum: f32,
+    ticks: u32,
+}
+
+pub const DESCRIPTOR_SIZE: usize = 4;
+
+impl Behaviour {
+    pub fn record(&mut self, x: f32, y: f32, tilt: f32, throttle: f32) {
+        self.final_x = x;
+        self.final_y = y;
+        self.tilt_sum += tilt;
+        self.throttle_sum += throttle;
+        self.ticks += 1;
+    }
+
+    pub fn average_tilt(&self) -> f32 {
+        if self.ticks == 0 {
+            return 0.0;
+        }
+        self.tilt_sum / self.ticks as f32
+    }
+
+    pub fn average_throttle(&self) -> f32 {
+        if self.ticks == 0 {
+            return 0.0;
+        }
+        self.throttle_sum / self.ticks as f32
+    }
+
+    // final x, final y, average tilt, average throttle
+    pub fn descriptor(&self) -> [f32; DESCRIPTOR_SIZE] {
+        [self.final_x, self.final_y, self.average_tilt(), self.average_throttle()]
+    }
+}
+
+fn distance(a: &[f32; DESCRIPTOR_SIZE], b: &[f32; DESCRIPTOR_SIZE]) -> f32 {
+    a.iter().zip(b.iter()).map(|(x, y)| (x - y).powi(2)).sum::<f32>().sqrt()
+}
+
+// Archive of behaviours seen so far. Novelty is the average distance to the k nearest
+// behaviours among the current population and the archive.
+pub struct NoveltyArchive {
+    behaviours: Vec<[f32; DESCRIPTOR_SIZE]>,
+    k: usize,
+    add_probability: f32,
+    max_size: usize,
+}
+
+impl NoveltyArchive {
+    pub fn new(k: usize, add_probability: f32, max_size: usize) -> NoveltyArchive {
+        NoveltyArchive {
+            behaviours: vec![],
+            k,
+            add_probability,
+            max_size,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.behaviours.len()
+    }
+
+    pub fn novelty_scores(&self, population: &[[f32; DESCRIPTOR_SIZE]]) -> Vec<f32> {
+        population.iter().enumerate().map(|(i, behaviour)| {
+            let mut distances: Vec<f32> = population.iter().enumerate()
+                .filter(|(j, _)| *j != i)
+                .map(|(_, other)| distance(behaviour, other))
+                .chain(self.behaviours.iter().map(|other| distance(behaviour, other)))
+                .collect();
+            distances.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            let nearest = &distances[..self.k.min(distances.len())];
+            if nearest.is_empty() {
+                return 0.0;
+            }
+            nearest.iter().sum::<f32>() / nearest.len() as f32
+        }).collect()
+    }
+
+    // Adds a random sample of the population to the archive, dropping the oldest entries when full
+    pub fn add_from(&mut self, population: &[[f32; DESCRIPTOR_SIZE]]) {
+        let mut rng = rand::thread_rng();
+        for behaviour in population {
+            if rng.gen::<f32>() < self.add_probability {
+                self.behaviours.push(*behaviour);
+            }
+        }
+        if self.behaviours.len() > self.max_size {
+            let overflow = self.behaviours.len() - self.max_size;
+            self.behaviours.drain(0..overflow);
+        }
+    }
+}
@@ -0,0 +1,316 @@
+use crate::point::Vector;
+
+// Layout of the raw observation built by `Ship::do_brain`, the range sensors follow it
+pub const RAW_STATE_SIZE: usize = 7;
+const ANGLE: usize = 0;
+const GOAL_X: usize = 1;
+const GOAL_Y: usize = 2;
+const ANGULAR_VELOCITY: usize = 3;
+const VELOCITY_X: usize = 4;
+const VELOCITY_Y: usize = 5;
+const FUEL: usize = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AngleEncoding {
+    // The `Vector::angle` of the ship axis, it jumps at +-pi
+    Raw,
+    // Sine and cosine of the same angle, which is the ship axis as a unit vector
+    SinCos,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Frame {
+    World,
+    // x along the ship axis, y across it
+    Body,
+}
+
+// How the raw observation is turned into network inputs
+#[derive(Debug, Clone)]
+pub struct ObservationConfig {
+    pub angle: AngleEncoding,
+    // Frame of the goal vector and the velocity
+    pub frame: Frame,
+    // The goal vector is shortened to at most this many metres, 0 keeps it as is
+    pub max_goal_distance: f32,
+    // Velocities are divided by these
+    pub velocity_scale: f32,
+    pub angular_velocity_scale: f32,
+    // Standardize every input with the running mean and deviation of the ship's `Normalizer`
+    pub normalize: bool,
+}
+
+impl Default for ObservationConfig {
+    // The raw observation as is
+    fn default() -> ObservationConfig {
+        ObservationConfig {
+            angle: AngleEncoding::Raw,
+            frame: Frame::World,
+            max_goal_distance: 0.,
+            velocity_scale: 1.,
+            angular_velocity_scale: 1.,
+            normalize: false,
+        }
+    }
+}
+
+// Normalized inputs are clipped to this many standard deviations
+const NORMALIZED_CLIP: f32 = 5.;
+
+impl ObservationConfig {
+    pub fn normalized() -> ObservationConfig {
+        ObservationConfig {
+            angle: AngleEncoding::SinCos,
+            frame: Frame::Body,
+            max_goal_distance: 3.,
+            velocity_scale: 5.,
+            angular_velocity_scale: 10.,
+            normalize: true,
+        }
+    }
+
+    // Network inputs for a raw observation with this many range sensors
+    pub fn size(&self, range_sensor_count: usize) -> usize {
+        let angle_size = match self.angle {
+            AngleEncoding::Raw => 1,
+            AngleEncoding::SinCos => 2,
+        };
+        RAW_STATE_SIZE - 1 + angle_size + range_sensor_count
+    }
+
+    pub fn encode(&self, raw: &[f32], normalizer: &mut Normalizer) -> Vec<f32> {
+        let angle = raw[ANGLE];
+        // Ship axis as a unit vector, see `Vector::angle`
+        let axis = Vector::new(angle.sin(), angle.cos());
+        let to_frame = |vector: Vector| match self.frame {
+            Frame::World => vector,
+            Frame::Body => Vector::new(
+                vector.x * axis.x + vector.y * axis.y,
+                axis.x * vector.y - axis.y * vector.x,
+            ),
+        };
+
+        let mut goal = to_frame(Vector::new(raw[GOAL_X], raw[GOAL_Y]));
+        let goal_distance = goal.length();
+        if self.max_goal_distance > 0. && goal_distance > self.max_goal_distance {
+            goal = goal.multiplied(self.max_goal_distance / goal_distance);
+        }
+        let velocity = to_frame(Vector::new(raw[VELOCITY_X], raw[VELOCITY_Y])).multiplied(1. / self.velocity_scale);
+
+        let mut inputs = match self.angle {
+            AngleEncoding::Raw => vec![angle],
+            AngleEncoding::SinCos => vec![axis.x, axis.y],
+        };
+        inputs.extend([
+            goal.x,
+            goal.y,
+            raw[ANGULAR_VELOCITY] / self.angular_velocity_scale,
+            velocity.x,
+            velocity.y,
+            raw[FUEL],
+        ]);
+        inputs.extend_from_slice(&raw[RAW_STATE_SIZE..]);
+
+        if self.normalize {
+            normalizer.update(&inputs);
+            inputs = normalizer.normalize(&inputs);
+        }
+        inputs
+    }
+}
+
+// Running mean and variance of every input (Welford's algorithm). Each ship keeps its own,
+// children inherit them, and they are saved next to the network.
+#[derive(Debug, Clone, Default)]
+pub struct Normalizer {
+    count: f64,
+    mean: Vec<f64>,
+    // Sum of squared differences from the mean
+    m2: Vec<f64>,
+}
+
+impl Normalizer {
+    pub fn update(&mut self, values: &[f32]) {
+        if self.mean.len() != values.len() {
+            *self = Normalizer {
+                count: 0.,
+                mean: vec![0.; values.len()],
+                m2: vec![0.; values.len()],
+            };
+        }
+        self.count += 1.;
+        for ((mean, m2), value) in self.mean.iter_mut().zip(self.m2.iter_mut()).zip(values) {
+            let value = *value as f64;
+            let delta = value - *mean;
+            *mean += delta / self.count;
+            *m2 += delta * (value - *mean);
+        }
+    }
+
+    pub fn normalize(&self, values: &[f32]) -> Vec<f32> {
+        if self.count < 2. || self.mean.len() != values.len() {
+            return values.to_vec();
+        }
+        values.iter().zip(self.mean.iter().zip(&self.m2)).map(|(value, (mean, m2))| {
+            let deviation = (m2 / (self.count - 1.)).sqrt().max(1e-3);
+            (((*value as f64 - mean) / deviation) as f32).clamp(-NORMALIZED_CLIP, NORMALIZED_CLIP)
+        }).collect()
+    }
+
+    // Adds the statistics of other values, as if they had gone through `update` (Chan et al.)
+    pub fn merge(&mut self, other: &Normalizer) {
+        if other.count == 0. {
+            return;
+        }
+        if self.count == 0. || self.mean.len() != other.mean.len() {
+            *self = other.clone();
+            return;
+        }
+        let count = self.count + other.count;
+        for (((mean, m2), other_mean), other_m2) in self.mean.iter_mut().zip(self.m2.iter_mut()).zip(&other.mean).zip(&other.m2) {
+            let delta = other_mean - *mean;
+            *mean += delta * other.count / count;
+            *m2 += other_m2 + delta * delta * self.count * other.count / count;
+        }
+        self.count = count;
+    }
+
+    // Statistics of the values that went into this normalizer after it was a copy of `start`,
+    // the inverse of `merge`
+    pub fn added_since(&self, start: &Normalizer) -> Normalizer {
+        if start.count == 0. || self.mean.len() != start.mean.len() {
+            return self.clone();
+        }
+        let count = self.count - start.count;
+        if count <= 0. {
+            return Normalizer::default();
+        }
+        let mut added = Normalizer { count, mean: vec![], m2: vec![] };
+        for (((mean, m2), start_mean), start_m2) in self.mean.iter().zip(&self.m2).zip(&start.mean).zip(&start.m2) {
+            let added_mean = (mean * self.count - start_mean * start.count) / count;
+            let delta = added_mean - start_mean;
+            added.mean.push(added_mean);
+            added.m2.push((m2 - start_m2 - delta * delta * start.count * count / self.count).max(0.));
+        }
+        added
+    }
+
+    // "normalizer <count>" followed by a line of means and a line of squared differences
+    pub fn to_text(&self) -> String {
+        let join = |values: &[f64]| values.iter().map(|value| value.to_string()).collect::<Vec<String>>().join(" ");
+        format!("normalizer {}\n{}\n{}", self.count, join(&self.mean), join(&self.m2))
+    }
+
+    // Reads the block written by `to_text` from anywhere in the text
+    pub fn from_text(text: &str) -> Option<Normalizer> {
+        let (_, block) = text.split_once("normalizer ")?;
+        let mut lines = block.lines();
+        let count: f64 = lines.next()?.trim().parse().ok()?;
+        let mut parse_line = || lines.next().unwrap_or("")
+            .split_whitespace()
+            .map(|value| value.parse().ok())
+            .collect::<Option<Vec<f64>>>();
+        let mean = parse_line()?;
+        let m2 = parse_line()?;
+        if mean.len() != m2.len() {
+            return None;
+        }
+        Some(Normalizer { count, mean, m2 })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fed(values: &[[f32; 2]]) -> Normalizer {
+        let mut normalizer = Normalizer::default();
+        for value in values {
+            normalizer.update(value);
+        }
+        normalizer
+    }
+
+    fn assert_same(a: &Normalizer, b: &Normalizer) {
+        assert_eq!(a.count, b.count);
+        for (x, y) in a.mean.iter().chain(&a.m2).zip(b.mean.iter().chain(&b.m2)) {
+            assert!((x - y).abs() < 1e-9, "{:?} instead of {:?}", a, b);
+        }
+    }
+
+    const VALUES: [[f32; 2]; 6] = [[1., -2.], [4., 0.5], [-3., 7.], [0., 0.], [2.5, -1.], [10., 3.]];
+
+    // Angle, goal, angular velocity, velocity, fuel and two range sensors
+    fn raw(angle: f32, goal: [f32; 2], velocity: [f32; 2]) -> Vec<f32> {
+        vec![angle, goal[0], goal[1], 0.5, velocity[0], velocity[1], 0.8, 1.5, 2.5]
+    }
+
+    fn close(a: &[f32], b: &[f32]) -> bool {
+        a.len() == b.len() && a.iter().zip(b).all(|(x, y)| (x - y).abs() < 1e-5)
+    }
+
+    #[test]
+    fn encoding_has_the_advertised_size() {
+        for angle in [AngleEncoding::Raw, AngleEncoding::SinCos] {
+            for frame in [Frame::World, Frame::Body] {
+                for normalize in [false, true] {
+                    let config = ObservationConfig { angle, frame, normalize, ..ObservationConfig::normalized() };
+                    let mut normalizer = Normalizer::default();
+                    for _ in 0..3 {
+                        let inputs = config.encode(&raw(0.3, [1., 2.], [0.5, -1.]), &mut normalizer);
+                        assert_eq!(inputs.len(), config.size(2), "{:?} {:?} normalize {}", angle, frame, normalize);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn body_frame_follows_the_ship_axis() {
+        let config = ObservationConfig { frame: Frame::Body, ..ObservationConfig::default() };
+        let mut normalizer = Normalizer::default();
+        // Axis along +x: +x is straight ahead and +y is to the side
+        let quarter = std::f32::consts::FRAC_PI_2;
+        let inputs = config.encode(&raw(quarter, [2., 0.], [0., -3.]), &mut normalizer);
+        assert!(close(&inputs, &[quarter, 2., 0., 0.5, 0., -3., 0.8, 1.5, 2.5]), "{:?}", inputs);
+        let inputs = config.encode(&raw(quarter, [0., 2.], [1., 0.]), &mut normalizer);
+        assert!(close(&inputs, &[quarter, 0., 2., 0.5, 1., 0., 0.8, 1.5, 2.5]), "{:?}", inputs);
+
+        // Axis along +y: world y becomes ahead and world x the negative side
+        let inputs = config.encode(&raw(0., [1., 2.], [3., 4.]), &mut normalizer);
+        assert!(close(&inputs, &[0., 2., -1., 0.5, 4., -3., 0.8, 1.5, 2.5]), "{:?}", inputs);
+    }
+
+    #[test]
+    fn far_goals_are_clipped_to_the_maximum_distance() {
+        let config = ObservationConfig { max_goal_distance: 5., velocity_scale: 2., ..ObservationConfig::default() };
+        let mut normalizer = Normalizer::default();
+        let far = config.encode(&raw(0., [6., 8.], [4., 0.]), &mut normalizer);
+        assert!(close(&far[1..3], &[3., 4.]), "{:?}", far);
+        assert!(close(&far[4..6], &[2., 0.]), "{:?}", far);
+        let near = config.encode(&raw(0., [0.6, 0.8], [0., 0.]), &mut normalizer);
+        assert!(close(&near[1..3], &[0.6, 0.8]), "{:?}", near);
+    }
+
+    #[test]
+    fn merging_equals_feeding_everything() {
+        let mut merged = fed(&VALUES[..2]);
+        merged.merge(&fed(&VALUES[2..]));
+        assert_same(&merged, &fed(&VALUES));
+
+        let mut empty = Normalizer::default();
+        empty.merge(&fed(&VALUES));
+        assert_same(&empty, &fed(&VALUES));
+    }
+
+    #[test]
+    fn added_values_are_recovered_from_a_copy() {
+        let start = fed(&VALUES[..3]);
+        let mut copy = start.clone();
+        for value in &VALUES[3..] {
+            copy.update(value);
+        }
+        assert_same(&copy.added_since(&start), &fed(&VALUES[3..]));
+        assert_eq!(start.added_since(&start).count, 0.);
+    }
+}
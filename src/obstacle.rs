@@ -0,0 +1,247 @@
+use crate::point::Vector;
+
+// Number of range sensors, spread evenly around the ship starting along its body
+pub const RAY_COUNT: usize = 8;
+
+#[derive(Debug, Clone)]
+pub enum Obstacle {
+    Circle { center: Vector, radius: f32 },
+    // Axis aligned, min is the top left corner
+    Rectangle { min: Vector, max: Vector },
+    // Corners in order, either winding
+    Polygon(Vec<Vector>),
+}
+
+fn cross(a: &Vector, b: &Vector) -> f32 {
+    a.x * b.y - a.y * b.x
+}
+
+fn dot(a: &Vector, b: &Vector) -> f32 {
+    a.x * b.x + a.y * b.y
+}
+
+// Distance along a unit direction from origin to the segment a-b, None if the ray misses it
+pub fn ray_segment(origin: &Vector, direction: &Vector, a: &Vector, b: &Vector) -> Option<f32> {
+    let edge = b.subtracted(a);
+    let denominator = cross(direction, &edge);
+    if denominator.abs() < 1e-9 {
+        return None;
+    }
+    let to_a = a.subtracted(origin);
+    let t = cross(&to_a, &edge) / denominator;
+    let u = cross(&to_a, direction) / denominator;
+    if t >= 0. && (0. ..=1.).contains(&u) {
+        Some(t)
+    } else {
+        None
+    }
+}
+
+fn segments_intersect(a: &Vector, b: &Vector, c: &Vector, d: &Vector) -> bool {
+    let ab = b.subtracted(a);
+    let cd = d.subtracted(c);
+    let side_c = cross(&ab, &c.subtracted(a));
+    let side_d = cross(&ab, &d.subtracted(a));
+    let side_a = cross(&cd, &a.subtracted(c));
+    let side_b = cross(&cd, &b.subtracted(c));
+    side_c * side_d <= 0. && side_a * side_b <= 0.
+}
+
+fn distance_to_segment(point: &Vector, a: &Vector, b: &Vector) -> f32 {
+    let ab = b.subtracted(a);
+    let length_squared = dot(&ab, &ab);
+    let t = if length_squared > 0. {
+        (dot(&point.subtracted(a), &ab) / length_squared).clamp(0., 1.)
+    } else {
+        0.
+    };
+    point.subtracted(&a.added(&ab.multiplied(t))).length()
+}
+
+impl Obstacle {
+    fn corners(&self) -> Vec<Vector> {
+        match self {
+            Obstacle::Circle { .. } => vec![],
+            Obstacle::Rectangle { min, max } => vec![
+                min.clone(),
+                Vector::new(max.x, min.y),
+                max.clone(),
+                Vector::new(min.x, max.y),
+            ],
+            Obstacle::Polygon(corners) => corners.clone(),
+        }
+    }
+
+    fn edges(corners: &[Vector]) -> impl Iterator<Item = (&Vector, &Vector)> {
+        corners.iter().zip(corners.iter().cycle().skip(1))
+    }
+
+    pub fn contains(&self, point: &Vector) -> bool {
+        match self {
+            Obstacle::Circle { center, radius } => point.subtracted(center).length() <= *radius,
+            _ => {
+                // Even-odd rule
+                let corners = self.corners();
+                let mut inside = false;
+                for (a, b) in Obstacle::edges(&corners) {
+                    if (a.y > point.y) != (b.y > point.y)
+                        && point.x < a.x + (point.y - a.y) / (b.y - a.y) * (b.x - a.x)
+                    {
+                        inside = !inside;
+                    }
+                }
+                inside
+            },
+        }
+    }
+
+    // True when any part of the segment a-b is inside the obstacle
+    pub fn intersects_segment(&self, a: &Vector, b: &Vector) -> bool {
+        match self {
+            Obstacle::Circle { center, radius } => distance_to_segment(center, a, b) <= *radius,
+            _ => {
+                let corners = self.corners();
+                self.contains(a) || Obstacle::edges(&corners).any(|(c, d)| segments_intersect(a, b, c, d))
+            },
+        }
+    }
+
+    pub fn ray_distance(&self, origin: &Vector, direction: &Vector) -> Option<f32> {
+        if self.contains(origin) {
+            return Some(0.);
+        }
+        match self {
+            Obstacle::Circle { center, radius } => {
+                let to_center = center.subtracted(origin);
+                let along = dot(&to_center, direction);
+                let miss_squared = dot(&to_center, &to_center) - along * along;
+                let inside_squared = radius * radius - miss_squared;
+                if along < 0. || inside_squared < 0. {
+                    return None;
+                }
+                Some(along - inside_squared.sqrt())
+            },
+            _ => {
+                let corners = self.corners();
+                Obstacle::edges(&corners)
+                    .filter_map(|(a, b)| ray_segment(origin, direction, a, b))
+                    .reduce(f32::min)
+            },
+        }
+    }
+}
+
+// Distance to the closest obstacle or extra segment along the ray, capped at max_range
+pub fn cast_ray(obstacles: &[Obstacle], segments: &[(Vector, Vector)], origin: &Vector, direction: &Vector, max_range: f32) -> f32 {
+    obstacles.iter()
+        .filter_map(|obstacle| obstacle.ray_distance(origin, direction))
+        .chain(segments.iter().filter_map(|(a, b)| ray_segment(origin, direction, a, b)))
+        .fold(max_range, f32::min)
+}
+
+// A few obstacles between the start and a goal to the right of it
+pub fn course() -> (Vec<Obstacle>, Vector) {
+    let obstacles = vec![
+        Obstacle::Circle { center: Vector::new(1.8, -0.5), radius: 0.5 },
+        Obstacle::Rectangle { min: Vector::new(1.2, 0.4), max: Vector::new(1.6, 1.8) },
+        Obstacle::Rectangle { min: Vector::new(2.6, -2.4), max: Vector::new(3., -1.) },
+        Obstacle::Polygon(vec![
+            Vector::new(2.5, 0.6),
+            Vector::new(3.3, 0.3),
+            Vector::new(3., 1.4),
+        ]),
+    ];
+    (obstacles, Vector::new(3.8, -0.3))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v(x: f32, y: f32) -> Vector {
+        Vector::new(x, y)
+    }
+
+    fn assert_distance(distance: Option<f32>, expected: f32) {
+        let distance = distance.expect("ray missed");
+        assert!((distance - expected).abs() < 1e-5, "{} instead of {}", distance, expected);
+    }
+
+    // A U open to the top, the notch is between x 1 and 2 above y 1
+    fn u_shape() -> Obstacle {
+        Obstacle::Polygon(vec![
+            v(0., 0.), v(3., 0.), v(3., 3.), v(2., 3.),
+            v(2., 1.), v(1., 1.), v(1., 3.), v(0., 3.),
+        ])
+    }
+
+    #[test]
+    fn rays_hit_the_near_side_and_miss_behind() {
+        let circle = Obstacle::Circle { center: v(3., 0.), radius: 1. };
+        assert_distance(circle.ray_distance(&v(0., 0.), &v(1., 0.)), 2.);
+        assert_eq!(circle.ray_distance(&v(0., 0.), &v(-1., 0.)), None);
+        assert_eq!(circle.ray_distance(&v(0., 0.), &v(0., 1.)), None);
+
+        let rectangle = Obstacle::Rectangle { min: v(2., -1.), max: v(4., 1.) };
+        assert_distance(rectangle.ray_distance(&v(0., 0.), &v(1., 0.)), 2.);
+        assert_eq!(rectangle.ray_distance(&v(0., 0.), &v(-1., 0.)), None);
+        assert_eq!(rectangle.ray_distance(&v(0., 2.), &v(1., 0.)), None);
+
+        let triangle = Obstacle::Polygon(vec![v(2., -1.), v(4., 0.), v(2., 1.)]);
+        assert_distance(triangle.ray_distance(&v(0., 0.), &v(1., 0.)), 2.);
+        assert_eq!(triangle.ray_distance(&v(0., 0.), &v(0., -1.)), None);
+
+        // From inside the U's notch the ray goes out the open top
+        assert_distance(u_shape().ray_distance(&v(1.5, 2.), &v(0., -1.)), 1.);
+        assert_eq!(u_shape().ray_distance(&v(1.5, 2.), &v(0., 1.)), None);
+        assert_distance(u_shape().ray_distance(&v(1.5, 2.), &v(1., 0.)), 0.5);
+    }
+
+    #[test]
+    fn closest_hit_wins_and_range_caps_the_rest() {
+        let obstacles = vec![
+            Obstacle::Circle { center: v(5., 0.), radius: 1. },
+            Obstacle::Rectangle { min: v(2., -1.), max: v(3., 1.) },
+        ];
+        let walls = [(v(1.5, -1.), v(1.5, 1.))];
+        assert_eq!(cast_ray(&obstacles, &[], &v(0., 0.), &v(1., 0.), 10.), 2.);
+        assert_eq!(cast_ray(&obstacles, &walls, &v(0., 0.), &v(1., 0.), 10.), 1.5);
+        assert_eq!(cast_ray(&obstacles, &walls, &v(0., 0.), &v(1., 0.), 1.), 1.);
+        assert_eq!(cast_ray(&obstacles, &[], &v(0., 0.), &v(0., 1.), 10.), 10.);
+    }
+
+    #[test]
+    fn segments_touching_or_crossing_intersect() {
+        // Crossing
+        assert!(segments_intersect(&v(0., 0.), &v(2., 2.), &v(0., 2.), &v(2., 0.)));
+        // Touching at an end point
+        assert!(segments_intersect(&v(0., 0.), &v(1., 0.), &v(1., 0.), &v(1., 1.)));
+        // The lines cross, the segments do not
+        assert!(!segments_intersect(&v(0., 0.), &v(1., 0.), &v(2., -1.), &v(2., 1.)));
+
+        let rectangle = Obstacle::Rectangle { min: v(0., 0.), max: v(2., 2.) };
+        assert!(rectangle.intersects_segment(&v(-1., 1.), &v(3., 1.)));
+        assert!(rectangle.intersects_segment(&v(-1., 1.), &v(0., 1.)));
+        assert!(!rectangle.intersects_segment(&v(-1., 3.), &v(3., 3.)));
+
+        let circle = Obstacle::Circle { center: v(0., 0.), radius: 1. };
+        assert!(circle.intersects_segment(&v(-1., 1.), &v(1., 1.)));
+        assert!(circle.intersects_segment(&v(-2., 0.), &v(2., 0.)));
+        assert!(!circle.intersects_segment(&v(-1., 1.5), &v(1., 1.5)));
+
+        // Over the notch of the U without touching it
+        assert!(!u_shape().intersects_segment(&v(1.2, 2.), &v(1.8, 2.)));
+        assert!(u_shape().intersects_segment(&v(0.5, 2.), &v(1.5, 2.)));
+    }
+
+    #[test]
+    fn concave_polygon_uses_the_even_odd_rule() {
+        let shape = u_shape();
+        assert!(shape.contains(&v(0.5, 2.)));
+        assert!(shape.contains(&v(2.5, 2.)));
+        assert!(shape.contains(&v(1.5, 0.5)));
+        assert!(!shape.contains(&v(1.5, 2.)));
+        assert!(!shape.contains(&v(4., 1.)));
+        assert!(!shape.contains(&v(-1., 2.)));
+    }
+}
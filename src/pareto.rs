@@ -0,0 +1,180 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+
+// Per-objective scores of a single ship. Every objective is minimized, same as `Ship::score`.
+#[derive(Debug, Clone, Default)]
+pub struct Objectives {
+    // Task part of the scalar score, without the fuel cost
+    pub tracking: f32,
+    // Throttle integrated over the episode, in seconds of full throttle
+    pub fuel: f32,
+    // Sum of absolute changes in the control outputs between ticks
+    pub smoothness: f32,
+    // Worst distance from the goal seen during the episode
+    pub robustness: f32,
+}
+
+pub const OBJECTIVE_COUNT: usize = 4;
+
+impl Objectives {
+    pub fn as_array(&self) -> [f32; OBJECTIVE_COUNT] {
+        [self.tracking, self.fuel, self.smoothness, self.robustness]
+    }
+}
+
+// True if a is at least as good as b in every objective and strictly better in one
+pub fn dominates(a: &[f32; OBJECTIVE_COUNT], b: &[f32; OBJECTIVE_COUNT]) -> bool {
+    let mut strictly_better = false;
+    for i in 0..OBJECTIVE_COUNT {
+        if a[i] > b[i] {
+            return false;
+        }
+        if a[i] < b[i] {
+            strictly_better = true;
+        }
+    }
+    strictly_better
+}
+
+// Fast non-dominated sort from NSGA-II. Returns fronts of indices, best front first.
+pub fn non_dominated_sort(points: &[[f32; OBJECTIVE_COUNT]]) -> Vec<Vec<usize>> {
+    let mut dominated_by: Vec<Vec<usize>> = vec![vec![]; points.len()];
+    let mut domination_count: Vec<usize> = vec![0; points.len()];
+    let mut fronts: Vec<Vec<usize>> = vec![vec![]];
+
+    for p in 0..points.len() {
+        for q in 0..points.len() {
+            if dominates(&points[p], &points[q]) {
+                dominated_by[p].push(q);
+            } else if dominates(&points[q], &points[p]) {
+                domination_count[p] += 1;
+            }
+        }
+        if domination_count[p] == 0 {
+            fronts[0].push(p);
+        }
+    }
+
+    let mut current = 0;
+    while !fronts[current].is_empty() {
+        let mut next_front = vec![];
+        for &p in &fronts[current] {
+            for &q in &dominated_by[p] {
+                domination_count[q] -= 1;
+                if domination_count[q] == 0 {
+                    next_front.push(q);
+                }
+            }
+        }
+        fronts.push(next_front);
+        current += 1;
+    }
+    fronts.pop();
+    fronts
+}
+
+// Crowding distance of each member of a front, in the same order as the front
+pub fn crowding_distance(points: &[[f32; OBJECTIVE_COUNT]], front: &[usize]) -> Vec<f32> {
+    let mut distances = vec![0.0; front.len()];
+    if front.len() <= 2 {
+        return vec![f32::INFINITY; front.len()];
+    }
+
+    let columns: Vec<Vec<f32>> = (0..OBJECTIVE_COUNT)
+        .map(|objective| front.iter().map(|&i| points[i][objective]).collect())
+        .collect();
+
+    for values in &columns {
+        let mut order: Vec<usize> = (0..front.len()).collect();
+        order.sort_by(|a, b| values[*a].partial_cmp(&values[*b]).unwrap());
+
+        let min = values[order[0]];
+        let max = values[order[order.len() - 1]];
+        distances[order[0]] = f32::INFINITY;
+        distances[order[order.len() - 1]] = f32::INFINITY;
+        if max - min <= 0.0 {
+            continue;
+        }
+
+        for k in 1..(order.len() - 1) {
+            distances[order[k]] += (values[order[k + 1]] - values[order[k - 1]]) / (max - min);
+        }
+    }
+    distances
+}
+
+// Indices ordered by the crowded comparison operator: lower front first, larger crowding distance first
+pub fn nsga2_order(points: &[[f32; OBJECTIVE_COUNT]]) -> Vec<usize> {
+    let mut order = vec![];
+    for front in non_dominated_sort(points) {
+        let distances = crowding_distance(points, &front);
+        let mut members: Vec<(usize, f32)> = front.into_iter().zip(distances).collect();
+        members.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+        order.extend(members.into_iter().map(|(index, _)| index));
+    }
+    order
+}
+
+// Appends the first Pareto front of a generation to a csv file. Generation 0 starts a new file.
+pub fn export_front(path: &str, generation: i32, points: &[[f32; OBJECTIVE_COUNT]]) -> std::io::Result<()> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(generation != 0)
+        .truncate(generation == 0)
+        .open(path)?;
+    if generation == 0 {
+        writeln!(file, "generation,tracking,fuel,smoothness,robustness")?;
+    }
+
+    let fronts = non_dominated_sort(points);
+    if let Some(front) = fronts.first() {
+        for &index in front {
+            let p = points[index];
+            writeln!(file, "{},{},{},{},{}", generation, p[0], p[1], p[2], p[3])?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Only the first two objectives differ
+    fn points() -> Vec<[f32; OBJECTIVE_COUNT]> {
+        [
+            [1.0, 5.0],
+            [2.0, 3.0],
+            [4.0, 2.0],
+            [5.0, 1.0],
+            // Behind 1
+            [3.0, 4.0],
+            [4.0, 5.0],
+            [6.0, 6.0],
+            // Behind 2 and 3
+            [6.0, 2.0],
+        ].iter().map(|[a, b]| [*a, *b, 0.0, 0.0]).collect()
+    }
+
+    #[test]
+    fn fronts_are_peeled_in_order() {
+        assert_eq!(non_dominated_sort(&points()), vec![vec![0, 1, 2, 3], vec![4, 7], vec![5], vec![6]]);
+        assert!(dominates(&points()[1], &points()[4]));
+        assert!(!dominates(&points()[1], &points()[1]));
+    }
+
+    #[test]
+    fn boundary_points_are_infinitely_far() {
+        let points = points();
+        // Objective ranges are 4, 1 is between 0 and 2 in both, 2 between 1 and 3
+        let distances = crowding_distance(&points, &[0, 1, 2, 3]);
+        assert_eq!(distances, vec![f32::INFINITY, 3.0 / 4.0 + 3.0 / 4.0, 3.0 / 4.0 + 2.0 / 4.0, f32::INFINITY]);
+        assert_eq!(crowding_distance(&points, &[4, 7]), vec![f32::INFINITY; 2]);
+    }
+
+    #[test]
+    fn order_goes_by_front_then_by_crowding() {
+        assert_eq!(nsga2_order(&points()), vec![0, 3, 1, 2, 4, 7, 5, 6]);
+    }
+}
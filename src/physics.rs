@@ -0,0 +1,69 @@
+use crate::integrator::IntegratorKind;
+use crate::ship_design::ShipDesign;
+
+#[derive(Debug, Clone)]
+pub enum BodyModel {
+    // Verlet points held at their rest distances by the links of the ship design
+    PointMasses,
+    // Rigid body with the motors mounted on the design points. Mass in kg, inertia in kg m^2.
+    RigidBody { mass: f32, inertia: f32 },
+}
+
+impl BodyModel {
+    // Rigid body with the mass spread evenly over the design points
+    pub fn rigid(mass: f32, design: &ShipDesign) -> BodyModel {
+        BodyModel::RigidBody { mass, inertia: design.inertia(mass) }
+    }
+}
+
+// Units are metres and seconds. The default ship is one metre long and the screen shows
+// 100 pixels per metre.
+#[derive(Debug, Clone)]
+pub struct PhysicsConfig {
+    // Length of one physics step in seconds
+    pub dt: f32,
+    // Physics steps taken for every control (network) tick
+    pub substeps: u32,
+    // Passes over the links per physics step, one is exact for a single link
+    pub constraint_iterations: u32,
+    // Downwards acceleration in m/s^2
+    pub gravity: f32,
+    // Acceleration a motor gives the point it is mounted on at full throttle and a max
+    // thrust of 1, in m/s^2
+    pub thrust_acceleration: f32,
+    pub body_model: BodyModel,
+    // Integrator for the points of the point mass model
+    pub integrator: IntegratorKind,
+}
+
+impl Default for PhysicsConfig {
+    // Matches the original per-tick constants (0.002 gravity, 0.005 thrust) at 60 ticks per second
+    fn default() -> PhysicsConfig {
+        PhysicsConfig {
+            dt: 1. / 120.,
+            substeps: 2,
+            constraint_iterations: 4,
+            gravity: 7.2,
+            thrust_acceleration: 18.,
+            body_model: BodyModel::PointMasses,
+            integrator: IntegratorKind::SemiImplicitEuler,
+        }
+    }
+}
+
+impl PhysicsConfig {
+    // Time between two control ticks
+    pub fn control_dt(&self) -> f32 {
+        self.dt * self.substeps as f32
+    }
+
+    // Number of control ticks in an episode of the given length
+    pub fn control_ticks(&self, seconds: f32) -> i32 {
+        (seconds / self.control_dt()).round() as i32
+    }
+
+    // Length of an episode of the given number of control ticks
+    pub fn episode_seconds(&self, ticks: i32) -> f32 {
+        ticks as f32 * self.control_dt()
+    }
+}
@@ -77,6 +77,15 @@ impl Vector {
     }
 }
 
+// The same angle in [-pi, pi]. Differences of atan2 angles jump by 2 pi where the branch
+// cut is crossed, wrapping them gives the short way round.
+pub fn wrap_angle(angle: f32) -> f32 {
+    use std::f32::consts::PI;
+    let wrapped = (angle + PI).rem_euclid(2. * PI) - PI;
+    // rem_euclid can round up to 2 pi for tiny negative inputs
+    if wrapped > PI { wrapped - 2. * PI } else { wrapped }
+}
+
 
 // #[derive(Debug, Clone, Copy)]
 // pub struct Point {
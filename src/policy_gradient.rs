@@ -0,0 +1,383 @@
+use rand::seq::SliceRandom;
+use crate::neural::{neural_net, GradientDescent, Optimizer};
+use crate::observation::Normalizer;
+use crate::random;
+
+#[derive(Debug, Clone)]
+pub struct PolicyGradientConfig {
+    // Episodes rolled out with the same policy before every update
+    pub episodes: usize,
+    pub discount: f32,
+    // Generalized advantage estimation, 1 uses the whole return minus the value
+    pub gae_lambda: f32,
+    // PPO clip range of the probability ratio, None is plain REINFORCE with a baseline
+    pub clip: Option<f32>,
+    // Passes over the rollouts per update
+    pub epochs: usize,
+    pub batch_size: usize,
+    pub policy_learning_rate: f32,
+    pub value_learning_rate: f32,
+    // Deviation of the exploration noise on every output at the start, it is learned after that
+    pub initial_std: f32,
+    // Bonus for keeping the exploration noise up
+    pub entropy_weight: f32,
+    // Rewards are multiplied by this so the value head sees returns around 1. Every control
+    // tick adds its score terms times the control timestep, 1/60 s by default
+    pub reward_scale: f32,
+}
+
+impl PolicyGradientConfig {
+    pub fn ppo() -> PolicyGradientConfig {
+        PolicyGradientConfig {
+            episodes: 64,
+            discount: 0.99,
+            gae_lambda: 0.95,
+            clip: Some(0.2),
+            epochs: 4,
+            batch_size: 256,
+            policy_learning_rate: 0.003,
+            value_learning_rate: 0.01,
+            initial_std: 0.3,
+            entropy_weight: 0.001,
+            reward_scale: 0.6,
+        }
+    }
+
+    pub fn reinforce() -> PolicyGradientConfig {
+        PolicyGradientConfig {
+            gae_lambda: 1.,
+            clip: None,
+            epochs: 1,
+            ..PolicyGradientConfig::ppo()
+        }
+    }
+}
+
+// What one ship did in an episode. The ship samples its motor commands around the network
+// outputs and stores them with the inputs and the rewards.
+#[derive(Debug, Clone)]
+pub struct Trajectory {
+    std: Vec<f32>,
+    inputs: Vec<Vec<f32>>,
+    actions: Vec<Vec<f32>>,
+    rewards: Vec<f32>,
+}
+
+impl Trajectory {
+    pub fn new(std: Vec<f32>) -> Trajectory {
+        Trajectory { std, inputs: vec![], actions: vec![], rewards: vec![] }
+    }
+
+    pub fn clear(&mut self) {
+        self.inputs.clear();
+        self.actions.clear();
+        self.rewards.clear();
+    }
+
+    // Samples an action around the network outputs and returns it clamped to what the
+    // motors take
+    pub fn explore(&mut self, inputs: Vec<f32>, mean: Vec<f32>) -> Vec<f32> {
+        let mut rng = rand::thread_rng();
+        let action: Vec<f32> = mean.iter().zip(&self.std).map(|(mean, std)| mean + std * random::normal(&mut rng)).collect();
+        self.inputs.push(inputs);
+        self.actions.push(action.clone());
+        action.iter().map(|value| value.clamp(0., 1.)).collect()
+    }
+
+    // Reward of the last action. Costs after the ship stopped flying go to its last action.
+    pub fn reward(&mut self, reward: f32) {
+        if self.rewards.len() < self.actions.len() {
+            self.rewards.push(reward);
+        } else if let Some(last) = self.rewards.last_mut() {
+            *last += reward;
+        }
+    }
+}
+
+// Linear estimate of the scaled return from the network inputs
+#[derive(Debug, Clone)]
+struct ValueHead {
+    // One per input and a bias
+    weights: Vec<f32>,
+    descent: GradientDescent,
+}
+
+impl ValueHead {
+    fn new(input_size: usize, learning_rate: f32) -> ValueHead {
+        ValueHead {
+            weights: vec![0.; input_size + 1],
+            descent: GradientDescent::new(Optimizer::adam(learning_rate), input_size + 1),
+        }
+    }
+
+    fn value(&self, inputs: &[f32]) -> f32 {
+        let bias = self.weights[self.weights.len() - 1];
+        inputs.iter().zip(&self.weights).map(|(input, weight)| input * weight).sum::<f32>() + bias
+    }
+}
+
+struct Sample<'a> {
+    inputs: &'a [f32],
+    action: &'a [f32],
+    log_probability: f32,
+    advantage: f32,
+    target: f32,
+}
+
+// On-policy training of a gaussian policy: the network outputs are the means and every
+// output has a learned deviation
+pub struct PolicyGradient {
+    pub policy: neural_net,
+    pub config: PolicyGradientConfig,
+    // Input statistics of all rollouts, every ship starts its rollout with a copy
+    pub normalizer: Normalizer,
+    log_std: Vec<f32>,
+    value: ValueHead,
+    policy_descent: GradientDescent,
+    log_std_descent: GradientDescent,
+}
+
+fn log_probability(action: &[f32], mean: &[f32], log_std: &[f32]) -> f32 {
+    action.iter().zip(mean).zip(log_std).map(|((action, mean), log_std)| {
+        let z = (action - mean) / log_std.exp();
+        -0.5 * z * z - log_std
+    }).sum()
+}
+
+// Derivatives of `log_probability` by every mean and every log deviation
+fn log_probability_gradient(action: &[f32], mean: &[f32], log_std: &[f32]) -> (Vec<f32>, Vec<f32>) {
+    action.iter().zip(mean).zip(log_std).map(|((action, mean), log_std)| {
+        let variance = (2. * log_std).exp();
+        ((action - mean) / variance, (action - mean).powi(2) / variance - 1.)
+    }).unzip()
+}
+
+impl PolicyGradient {
+    pub fn new(policy: &neural_net, config: PolicyGradientConfig) -> PolicyGradient {
+        let output_size = policy.output_size();
+        PolicyGradient {
+            policy: policy.clone(),
+            normalizer: Normalizer::default(),
+            log_std: vec![config.initial_std.ln(); output_size],
+            value: ValueHead::new(policy.input_size(), config.value_learning_rate),
+            policy_descent: GradientDescent::new(Optimizer::adam(config.policy_learning_rate), policy.weight_count()),
+            log_std_descent: GradientDescent::new(Optimizer::adam(config.policy_learning_rate), output_size),
+            config,
+        }
+    }
+
+    pub fn std(&self) -> Vec<f32> {
+        self.log_std.iter().map(|log_std| log_std.exp()).collect()
+    }
+
+    // Advantages and value targets of every step, per trajectory
+    fn estimate_advantages(&self, trajectory: &Trajectory) -> (Vec<f32>, Vec<f32>) {
+        let values: Vec<f32> = trajectory.inputs.iter().map(|inputs| self.value.value(inputs)).collect();
+        let mut advantages = vec![0.; trajectory.rewards.len()];
+        let mut running = 0.;
+        for t in (0..trajectory.rewards.len()).rev() {
+            // The episode ends after the last step
+            let next_value = values.get(t + 1).copied().unwrap_or(0.);
+            let reward = trajectory.rewards[t] * self.config.reward_scale;
+            let delta = reward + self.config.discount * next_value - values[t];
+            running = delta + self.config.discount * self.config.gae_lambda * running;
+            advantages[t] = running;
+        }
+        let targets = advantages.iter().zip(&values).map(|(advantage, value)| advantage + value).collect();
+        (advantages, targets)
+    }
+
+    // One update from the rollouts of the current policy
+    pub fn update(&mut self, trajectories: &[Trajectory]) {
+        let mut samples: Vec<Sample> = vec![];
+        for trajectory in trajectories {
+            let (advantages, targets) = self.estimate_advantages(trajectory);
+            for t in 0..trajectory.rewards.len() {
+                let mean = self.policy.outputs(&trajectory.inputs[t]);
+                samples.push(Sample {
+                    inputs: &trajectory.inputs[t],
+                    action: &trajectory.actions[t],
+                    log_probability: log_probability(&trajectory.actions[t], &mean, &self.log_std),
+                    advantage: advantages[t],
+                    target: targets[t],
+                });
+            }
+        }
+        if samples.is_empty() {
+            return;
+        }
+
+        // Advantages are standardized over the whole batch
+        let count = samples.len() as f32;
+        let mean_advantage = samples.iter().map(|sample| sample.advantage).sum::<f32>() / count;
+        let deviation = (samples.iter().map(|sample| (sample.advantage - mean_advantage).powi(2)).sum::<f32>() / count).sqrt().max(1e-6);
+        for sample in &mut samples {
+            sample.advantage = (sample.advantage - mean_advantage) / deviation;
+        }
+
+        let mut rng = rand::thread_rng();
+        let mut order: Vec<usize> = (0..samples.len()).collect();
+        let mut weights = self.policy.get_weights_flat();
+        let mut value_loss = 0.;
+        let mut clipped = 0;
+        for _ in 0..self.config.epochs {
+            order.shuffle(&mut rng);
+            value_loss = 0.;
+            clipped = 0;
+            for batch in order.chunks(self.config.batch_size.max(1)) {
+                let batch_size = batch.len() as f32;
+                let mut policy_gradient = vec![0.; weights.len()];
+                let mut log_std_gradient = vec![-self.config.entropy_weight; self.log_std.len()];
+                let mut value_gradient = vec![0.; self.value.weights.len()];
+
+                for &index in batch {
+                    let sample = &samples[index];
+                    let mean = self.policy.outputs(sample.inputs);
+                    let ratio = (log_probability(sample.action, &mean, &self.log_std) - sample.log_probability).exp();
+                    let outside_clip = match self.config.clip {
+                        Some(clip) => (sample.advantage > 0. && ratio > 1. + clip) || (sample.advantage < 0. && ratio < 1. - clip),
+                        None => false,
+                    };
+                    if outside_clip {
+                        clipped += 1;
+                    } else {
+                        // Loss is -ratio * advantage, REINFORCE has ratio 1 on its single pass
+                        let scale = -ratio * sample.advantage / batch_size;
+                        let (mean_gradient, std_gradient) = log_probability_gradient(sample.action, &mean, &self.log_std);
+                        let output_gradient: Vec<f32> = mean_gradient.iter().map(|gradient| scale * gradient).collect();
+                        for (total, gradient) in log_std_gradient.iter_mut().zip(std_gradient) {
+                            *total += scale * gradient;
+                        }
+                        for (total, value) in policy_gradient.iter_mut().zip(self.policy.backpropagate(&output_gradient)) {
+                            *total += value;
+                        }
+                    }
+
+                    let error = self.value.value(sample.inputs) - sample.target;
+                    value_loss += error * error / count;
+                    let input_count = sample.inputs.len();
+                    for (gradient, input) in value_gradient.iter_mut().zip(sample.inputs) {
+                        *gradient += 2. * error * input / batch_size;
+                    }
+                    value_gradient[input_count] += 2. * error / batch_size;
+                }
+
+                self.policy_descent.step(&mut weights, &policy_gradient);
+                self.policy.set_weights_flat(&weights);
+                self.log_std_descent.step(&mut self.log_std, &log_std_gradient);
+                for log_std in &mut self.log_std {
+                    *log_std = log_std.clamp(-4., 0.);
+                }
+                self.value.descent.step(&mut self.value.weights, &value_gradient);
+            }
+        }
+        println!(
+            "Policy gradient: {} samples, std {:?}, value loss {}, clipped {}",
+            samples.len(),
+            self.std(),
+            value_loss,
+            clipped,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::neural::Initializer;
+
+    fn assert_close(actual: &[f32], expected: &[f32], tolerance: f32) {
+        assert_eq!(actual.len(), expected.len());
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < tolerance, "{:?} instead of {:?}", actual, expected);
+        }
+    }
+
+    // Central difference of f at every coordinate of x
+    fn numeric_gradient(x: &[f32], f: impl Fn(&[f32]) -> f32) -> Vec<f32> {
+        let step = 1e-3;
+        (0..x.len()).map(|i| {
+            let mut plus = x.to_vec();
+            let mut minus = x.to_vec();
+            plus[i] += step;
+            minus[i] -= step;
+            (f(&plus) - f(&minus)) / (2. * step)
+        }).collect()
+    }
+
+    #[test]
+    fn log_probability_gradients_match_finite_differences() {
+        let action = [0.9, 0.1, 0.45];
+        let mean = [0.6, 0.3, 0.5];
+        let log_std = [-1., -0.5, -2.];
+        let (mean_gradient, std_gradient) = log_probability_gradient(&action, &mean, &log_std);
+        assert_close(&mean_gradient, &numeric_gradient(&mean, |mean| log_probability(&action, mean, &log_std)), 1e-2);
+        assert_close(&std_gradient, &numeric_gradient(&log_std, |log_std| log_probability(&action, &mean, log_std)), 1e-2);
+
+        // Through the network, as `update` does it
+        let mut net = neural_net::with_initializer(vec![2, 3, 3], Initializer::Xavier);
+        let inputs = [0.3, -0.7];
+        let weights = net.get_weights_flat();
+        let (mean_gradient, _) = log_probability_gradient(&action, &net.outputs(&inputs), &log_std);
+        let weight_gradient = net.backpropagate(&mean_gradient);
+        let numeric = numeric_gradient(&weights, |weights| {
+            let mut net = net.clone();
+            net.set_weights_flat(weights);
+            log_probability(&action, &net.outputs(&inputs), &log_std)
+        });
+        assert_close(&weight_gradient, &numeric, 1e-2);
+    }
+
+    fn trainer(gae_lambda: f32) -> PolicyGradient {
+        let config = PolicyGradientConfig { discount: 0.9, gae_lambda, reward_scale: 1., ..PolicyGradientConfig::ppo() };
+        let mut trainer = PolicyGradient::new(&neural_net::new(vec![1, 1]), config);
+        // value(x) = 0.5 x + 0.1
+        trainer.value.weights = vec![0.5, 0.1];
+        trainer
+    }
+
+    #[test]
+    fn advantages_of_a_short_episode() {
+        let trajectory = Trajectory {
+            std: vec![0.3],
+            inputs: vec![vec![1.], vec![2.], vec![0.]],
+            actions: vec![vec![0.5]; 3],
+            rewards: vec![1., 0., 2.],
+        };
+        // Values 0.6, 1.1, 0.1 and deltas 1 + 0.9 * 1.1 - 0.6, 0 + 0.9 * 0.1 - 1.1, 2 - 0.1
+        let (advantages, targets) = trainer(0.5).estimate_advantages(&trajectory);
+        // Each step adds 0.9 * 0.5 of the next advantage
+        assert_close(&advantages, &[1.39 + 0.45 * -0.155, -1.01 + 0.45 * 1.9, 1.9], 1e-5);
+        assert_close(&targets, &[1.32025 + 0.6, -0.155 + 1.1, 1.9 + 0.1], 1e-5);
+
+        // With lambda 1 the targets are the discounted returns
+        let (advantages, targets) = trainer(1.).estimate_advantages(&trajectory);
+        assert_close(&advantages, &[2.62 - 0.6, 1.8 - 1.1, 2. - 0.1], 1e-5);
+        assert_close(&targets, &[2.62, 1.8, 2.], 1e-5);
+    }
+
+    // One step episodes with a fixed input, the best action is 0.8
+    fn bandit_mean(config: PolicyGradientConfig) -> f32 {
+        let mut trainer = PolicyGradient::new(&neural_net::with_initializer(vec![1, 1], Initializer::Zeros), config);
+        for _ in 0..40 {
+            let trajectories: Vec<Trajectory> = (0..trainer.config.episodes).map(|_| {
+                let mut trajectory = Trajectory::new(trainer.std());
+                let mean = trainer.policy.outputs(&[1.]);
+                let action = trajectory.explore(vec![1.], mean);
+                trajectory.reward(-(action[0] - 0.8).powi(2));
+                trajectory
+            }).collect();
+            trainer.update(&trajectories);
+        }
+        trainer.policy.outputs(&[1.])[0]
+    }
+
+    #[test]
+    fn ppo_and_reinforce_move_towards_the_best_action() {
+        // The mean starts at 0.5
+        let ppo = bandit_mean(PolicyGradientConfig { policy_learning_rate: 0.02, ..PolicyGradientConfig::ppo() });
+        assert!((ppo - 0.8).abs() < 0.1, "ppo mean {}", ppo);
+        let reinforce = bandit_mean(PolicyGradientConfig { policy_learning_rate: 0.02, ..PolicyGradientConfig::reinforce() });
+        assert!((reinforce - 0.8).abs() < 0.1, "reinforce mean {}", reinforce);
+    }
+}
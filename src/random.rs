@@ -0,0 +1,8 @@
+use rand::Rng;
+
+// Standard normal sample using the Box-Muller transform
+pub fn normal<R: Rng>(rng: &mut R) -> f32 {
+    let u1: f32 = rng.gen::<f32>().max(f32::MIN_POSITIVE);
+    let u2: f32 = rng.gen::<f32>();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * std::f32::consts::PI * u2).cos()
+}
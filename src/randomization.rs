@@ -0,0 +1,125 @@
+use rand::Rng;
+
+// Closed interval a parameter is drawn from, uniformly
+#[derive(Debug, Clone, Copy)]
+pub struct Range {
+    pub min: f32,
+    pub max: f32,
+}
+
+impl Range {
+    pub fn fixed(value: f32) -> Range {
+        Range { min: value, max: value }
+    }
+
+    fn sample<R: Rng>(&self, rng: &mut R) -> f32 {
+        if self.max <= self.min {
+            return self.min;
+        }
+        rng.gen_range(self.min..=self.max)
+    }
+
+    fn is_fixed(&self) -> bool {
+        self.max <= self.min
+    }
+}
+
+// Ranges the physical parameters of an episode are sampled from in `Ship::reset`.
+// The scales multiply the nominal values of the scenario.
+#[derive(Debug, Clone)]
+pub struct DomainRandomization {
+    pub gravity_scale: Range,
+    pub thrust_scale: Range,
+    // Scales every distance of the ship design
+    pub length_scale: Range,
+    pub mass_scale: Range,
+    // Seconds added to the thrust time constant of the motors
+    pub thrust_lag: Range,
+    // Standard deviation of the noise added to every motor command, per physics step
+    pub actuator_noise: Range,
+}
+
+impl Default for DomainRandomization {
+    // Every episode uses the nominal values
+    fn default() -> DomainRandomization {
+        DomainRandomization {
+            gravity_scale: Range::fixed(1.),
+            thrust_scale: Range::fixed(1.),
+            length_scale: Range::fixed(1.),
+            mass_scale: Range::fixed(1.),
+            thrust_lag: Range::fixed(0.),
+            actuator_noise: Range::fixed(0.),
+        }
+    }
+}
+
+impl DomainRandomization {
+    pub fn wide() -> DomainRandomization {
+        DomainRandomization {
+            gravity_scale: Range { min: 0.8, max: 1.2 },
+            thrust_scale: Range { min: 0.8, max: 1.2 },
+            length_scale: Range { min: 0.8, max: 1.25 },
+            mass_scale: Range { min: 0.8, max: 1.3 },
+            thrust_lag: Range { min: 0., max: 0.1 },
+            actuator_noise: Range { min: 0., max: 0.05 },
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        ![
+            self.gravity_scale,
+            self.thrust_scale,
+            self.length_scale,
+            self.mass_scale,
+            self.thrust_lag,
+            self.actuator_noise,
+        ].iter().all(Range::is_fixed)
+    }
+
+    pub fn sample(&self) -> EpisodeConditions {
+        let mut rng = rand::thread_rng();
+        EpisodeConditions {
+            gravity_scale: self.gravity_scale.sample(&mut rng),
+            thrust_scale: self.thrust_scale.sample(&mut rng),
+            length_scale: self.length_scale.sample(&mut rng),
+            mass_scale: self.mass_scale.sample(&mut rng),
+            thrust_lag: self.thrust_lag.sample(&mut rng),
+            actuator_noise: self.actuator_noise.sample(&mut rng),
+        }
+    }
+}
+
+// Parameters drawn for one episode
+#[derive(Debug, Clone)]
+pub struct EpisodeConditions {
+    pub gravity_scale: f32,
+    pub thrust_scale: f32,
+    pub length_scale: f32,
+    pub mass_scale: f32,
+    pub thrust_lag: f32,
+    pub actuator_noise: f32,
+}
+
+impl Default for EpisodeConditions {
+    fn default() -> EpisodeConditions {
+        EpisodeConditions {
+            gravity_scale: 1.,
+            thrust_scale: 1.,
+            length_scale: 1.,
+            mass_scale: 1.,
+            thrust_lag: 0.,
+            actuator_noise: 0.,
+        }
+    }
+}
+
+impl EpisodeConditions {
+    pub const CSV_HEADER: &'static str = "gravity_scale,thrust_scale,length_scale,mass_scale,thrust_lag,actuator_noise";
+
+    pub fn csv_fields(&self) -> String {
+        format!(
+            "{},{},{},{},{},{}",
+            self.gravity_scale, self.thrust_scale, self.length_scale, self.mass_scale, self.thrust_lag, self.actuator_noise
+        )
+    }
+}
@@ -0,0 +1,168 @@
+use crate::point::{Vector, wrap_angle};
+
+// Rigid body in 2D. The body frame has x along the ship axis, from the second design point
+// to the first one, so at angle 0 the ship lies flat with the first point on the right.
+#[derive(Debug, Clone)]
+pub struct RigidBody {
+    pub position: Vector,
+    pub angle: f32,
+    pub velocity: Vector,
+    pub angular_velocity: f32,
+    pub mass: f32,
+    pub inertia: f32,
+}
+
+// 2D cross product, the z component of a x b
+fn cross(a: &Vector, b: &Vector) -> f32 {
+    a.x * b.y - a.y * b.x
+}
+
+impl RigidBody {
+    // Builds the body from the ship points and their positions one step earlier. The points
+    // are equal masses and the first two give the axis.
+    pub fn from_points(points: &[Vector], points_last: &[Vector], dt: f32, mass: f32, inertia: f32) -> RigidBody {
+        let mean = |points: &[Vector]| points.iter()
+            .fold(Vector::new(0., 0.), |sum, point| sum.added(point))
+            .multiplied(1. / points.len() as f32);
+        let position = mean(points);
+        let position_last = mean(points_last);
+        let axis = points[0].subtracted(&points[1]);
+        let axis_last = points_last[0].subtracted(&points_last[1]);
+        let angle = axis.y.atan2(axis.x);
+        let angle_change = wrap_angle(angle - axis_last.y.atan2(axis_last.x));
+
+        RigidBody {
+            velocity: position.subtracted(&position_last).multiplied(1. / dt),
+            position,
+            angle,
+            angular_velocity: angle_change / dt,
+            mass,
+            inertia,
+        }
+    }
+
+    // Body frame offset rotated into the world frame
+    pub fn rotated(&self, local: &Vector) -> Vector {
+        let (sin, cos) = self.angle.sin_cos();
+        Vector::new(local.x * cos - local.y * sin, local.x * sin + local.y * cos)
+    }
+
+    pub fn world_point(&self, local: &Vector) -> Vector {
+        self.position.added(&self.rotated(local))
+    }
+
+    // Velocity of a point fixed to the body, given as a world frame offset from the center
+    pub fn point_velocity(&self, offset: &Vector) -> Vector {
+        self.velocity.added(&Vector::new(-offset.y, offset.x).multiplied(self.angular_velocity))
+    }
+
+    // Semi-implicit Euler step. Forces are (world force, world offset from the center of mass)
+    // pairs; the offset gives the torque.
+    pub fn step(&mut self, forces: &[(Vector, Vector)], gravity: &Vector, dt: f32) {
+        let mut total_force = gravity.multiplied(self.mass);
+        let mut torque = 0.;
+        for (force, offset) in forces {
+            total_force.add(force);
+            torque += cross(offset, force);
+        }
+
+        self.velocity.add(&total_force.multiplied(dt / self.mass));
+        self.angular_velocity += torque / self.inertia * dt;
+        self.position.add(&self.velocity.multiplied(dt));
+        self.angle += self.angular_velocity * dt;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn body_at_rest() -> RigidBody {
+        RigidBody {
+            position: Vector::new(1., 2.),
+            angle: 0.3,
+            velocity: Vector::new(0., 0.),
+            angular_velocity: 0.,
+            mass: 2.,
+            inertia: 0.5,
+        }
+    }
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 1e-5, "{} instead of {}", actual, expected);
+    }
+
+    #[test]
+    fn pure_torque_spins_by_torque_over_inertia() {
+        let mut body = body_at_rest();
+        // Opposite pushes at both ends, a torque of 2 and no net force
+        let forces = [
+            (Vector::new(0., 1.), Vector::new(1., 0.)),
+            (Vector::new(0., -1.), Vector::new(-1., 0.)),
+        ];
+        let dt = 0.01;
+        body.step(&forces, &Vector::new(0., 0.), dt);
+        assert_close(body.angular_velocity, 2. / 0.5 * dt);
+        assert_close(body.angle, 0.3 + 2. / 0.5 * dt * dt);
+        assert_eq!((body.velocity.x, body.velocity.y), (0., 0.));
+    }
+
+    #[test]
+    fn off_center_force_pushes_and_spins() {
+        let mut body = body_at_rest();
+        let dt = 0.01;
+        body.step(&[(Vector::new(0., 1.), Vector::new(1., 0.))], &Vector::new(0., 0.), dt);
+        assert_close(body.velocity.y, 1. / 2. * dt);
+        assert_close(body.angular_velocity, 1. / 0.5 * dt);
+    }
+
+    #[test]
+    fn centred_force_gives_no_spin() {
+        let mut body = body_at_rest();
+        let dt = 0.01;
+        // At the center, and along the line through the center
+        let forces = [
+            (Vector::new(3., -1.), Vector::new(0., 0.)),
+            (Vector::new(1., 0.), Vector::new(0.5, 0.)),
+        ];
+        body.step(&forces, &Vector::new(0., 9.), dt);
+        assert_eq!(body.angular_velocity, 0.);
+        assert_eq!(body.angle, 0.3);
+        assert_close(body.velocity.x, 4. / 2. * dt);
+        assert_close(body.velocity.y, (-1. / 2. + 9.) * dt);
+    }
+
+    #[test]
+    fn points_round_trip_keeps_positions_and_velocities() {
+        let dt = 0.01;
+        let body = RigidBody {
+            position: Vector::new(1., -0.5),
+            angle: 3.16,
+            velocity: Vector::new(0.4, -2.),
+            angular_velocity: 5.,
+            mass: 1.,
+            inertia: 0.3,
+        };
+        let before = RigidBody {
+            position: body.position.subtracted(&body.velocity.multiplied(dt)),
+            angle: body.angle - body.angular_velocity * dt,
+            ..body.clone()
+        };
+        // Centred on the origin with the axis along x, as `ShipDesign::local_points` gives them
+        let local = [Vector::new(0.5, 0.1), Vector::new(-0.5, 0.1), Vector::new(0., -0.2)];
+        let points: Vec<Vector> = local.iter().map(|point| body.world_point(point)).collect();
+        let points_last: Vec<Vector> = local.iter().map(|point| before.world_point(point)).collect();
+
+        // The angle crosses pi between the two steps
+        let rebuilt = RigidBody::from_points(&points, &points_last, dt, body.mass, body.inertia);
+        assert_close(rebuilt.position.x, body.position.x);
+        assert_close(rebuilt.position.y, body.position.y);
+        assert_close(wrap_angle(rebuilt.angle - body.angle), 0.);
+        assert!((rebuilt.velocity.x - body.velocity.x).abs() < 1e-3 && (rebuilt.velocity.y - body.velocity.y).abs() < 1e-3);
+        assert!((rebuilt.angular_velocity - body.angular_velocity).abs() < 1e-2, "{}", rebuilt.angular_velocity);
+        for (point, local) in points.iter().zip(&local) {
+            let again = rebuilt.world_point(local);
+            assert!(again.subtracted(point).length() < 1e-5);
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use crate::fuel::FuelConfig;
+use crate::motor::MotorConfig;
+use crate::neural::Initializer;
+use crate::obstacle::Obstacle;
+use crate::observation::ObservationConfig;
+use std::sync::Arc;
+use crate::physics::PhysicsConfig;
+use crate::randomization::DomainRandomization;
+use crate::sensor::SensorConfig;
+use crate::point::Vector;
+use crate::ship_design::ShipDesign;
+use crate::terrain::Terrain;
+use crate::wind::AeroConfig;
+
+// What the ships are trained to do
+#[derive(Debug, Clone, Default)]
+pub enum Task {
+    // Follow a goal moving around the start
+    #[default]
+    Tracking,
+    // Touch down softly on the target pad of the terrain
+    Landing,
+    // Reach a fixed goal without touching any obstacle
+    Navigation { goal: Vector },
+}
+
+// Everything about the world the ships are simulated in
+#[derive(Debug, Clone)]
+pub struct Scenario {
+    // Shared with every ship built for the scenario
+    pub ship: Arc<ShipDesign>,
+    pub physics: PhysicsConfig,
+    // Per episode variation of the physics, also shared with the ships
+    pub randomization: Arc<DomainRandomization>,
+    pub motors: MotorConfig,
+    pub fuel: FuelConfig,
+    pub aero: AeroConfig,
+    // Open space when None
+    pub terrain: Option<Terrain>,
+    // Touching any of these kills the ship, the range sensors see them and the terrain
+    pub obstacles: Vec<Obstacle>,
+    pub sensor_range: f32,
+    // Noise, delay and dropouts between the ship state and the network
+    pub sensors: SensorConfig,
+    // How the sensor readings are encoded into network inputs
+    pub observation: ObservationConfig,
+    // How the weights of the networks built by `Ship::new` are drawn
+    pub initializer: Initializer,
+    pub task: Task,
+}
+
+impl Default for Scenario {
+    fn default() -> Scenario {
+        Scenario {
+            ship: Arc::new(ShipDesign::default()),
+            physics: PhysicsConfig::default(),
+            randomization: Arc::new(DomainRandomization::default()),
+            motors: MotorConfig::default(),
+            fuel: FuelConfig::default(),
+            aero: AeroConfig::default(),
+            terrain: None,
+            obstacles: vec![],
+            sensor_range: 3.,
+            sensors: SensorConfig::default(),
+            observation: ObservationConfig::default(),
+            initializer: Initializer::default(),
+            task: Task::default(),
+        }
+    }
+}
+
+impl Scenario {
+    // Goal of tasks that do not move it around, None when tracking
+    pub fn fixed_goal(&self) -> Option<Vector> {
+        match (&self.task, &self.terrain) {
+            (Task::Landing, Some(terrain)) => Some(terrain.pad_center(terrain.target_pad)),
+            (Task::Navigation { goal }, _) => Some(goal.clone()),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,199 @@
+use std::collections::VecDeque;
+use rand::Rng;
+use crate::random;
+
+// How one observation channel is corrupted
+#[derive(Debug, Clone, Default)]
+pub struct ChannelNoise {
+    // Standard deviation of the white noise added every tick
+    pub noise: f32,
+    // The bias is a random walk, this is its standard deviation after one second
+    pub bias_drift: f32,
+    // Readings are rounded to multiples of this, 0 keeps them continuous
+    pub quantization: f32,
+}
+
+impl ChannelNoise {
+    fn new(noise: f32, bias_drift: f32, quantization: f32) -> ChannelNoise {
+        ChannelNoise { noise, bias_drift, quantization }
+    }
+}
+
+// Sits between the true state of the ship and the network inputs
+#[derive(Debug, Clone, Default)]
+pub struct SensorConfig {
+    // Per observation channel, channels past the end are exact
+    pub channels: Vec<ChannelNoise>,
+    // Observations reach the network this many control ticks late
+    pub latency_ticks: usize,
+    // Chance per tick and channel that a reading is lost and the last delivered one is kept
+    pub dropout: f32,
+}
+
+// What the sensors of one ship remember between ticks
+#[derive(Debug, Clone, Default)]
+pub struct SensorState {
+    biases: Vec<f32>,
+    pending: VecDeque<Vec<f32>>,
+    last_delivered: Vec<f32>,
+}
+
+impl SensorConfig {
+    // Channel layout follows `Ship::do_brain`: ship angle, goal x and y distance, angular
+    // velocity, x and y velocity, fuel and then the range sensors
+    pub fn noisy(range_sensor_count: usize) -> SensorConfig {
+        let mut channels = vec![
+            ChannelNoise::new(0.02, 0.01, 0.),
+            ChannelNoise::new(0.05, 0.02, 0.),
+            ChannelNoise::new(0.05, 0.02, 0.),
+            ChannelNoise::new(0.2, 0.05, 0.),
+            ChannelNoise::new(0.1, 0.05, 0.),
+            ChannelNoise::new(0.1, 0.05, 0.),
+            ChannelNoise::new(0.01, 0., 0.05),
+        ];
+        channels.extend((0..range_sensor_count).map(|_| ChannelNoise::new(0.02, 0., 1. / 32.)));
+        SensorConfig {
+            channels,
+            latency_ticks: 2,
+            dropout: 0.02,
+        }
+    }
+
+    fn is_perfect(&self) -> bool {
+        self.latency_ticks == 0
+            && self.dropout <= 0.
+            && self.channels.iter().all(|channel| {
+                channel.noise <= 0. && channel.bias_drift <= 0. && channel.quantization <= 0.
+            })
+    }
+
+    // Turns the true observation of this tick into what the network gets to see
+    pub fn observe(&self, state: &mut SensorState, truth: Vec<f32>, dt: f32) -> Vec<f32> {
+        if self.is_perfect() {
+            return truth;
+        }
+        self.observe_with(&mut rand::thread_rng(), state, truth, dt)
+    }
+
+    fn observe_with<R: Rng>(&self, rng: &mut R, state: &mut SensorState, truth: Vec<f32>, dt: f32) -> Vec<f32> {
+        state.biases.resize(truth.len(), 0.);
+
+        let measured: Vec<f32> = truth.iter().enumerate().map(|(i, value)| {
+            let channel = match self.channels.get(i) {
+                Some(channel) => channel,
+                None => return *value,
+            };
+            state.biases[i] += random::normal(rng) * channel.bias_drift * dt.sqrt();
+            let reading = value + state.biases[i] + random::normal(rng) * channel.noise;
+            if channel.quantization > 0. {
+                (reading / channel.quantization).round() * channel.quantization
+            } else {
+                reading
+            }
+        }).collect();
+
+        // Until the buffer fills up the first observation is repeated
+        state.pending.push_back(measured);
+        while state.pending.len() > self.latency_ticks + 1 {
+            state.pending.pop_front();
+        }
+        let mut delivered = state.pending[0].clone();
+
+        if state.last_delivered.len() == delivered.len() {
+            for (value, last) in delivered.iter_mut().zip(&state.last_delivered) {
+                if rng.gen::<f32>() < self.dropout {
+                    *value = *last;
+                }
+            }
+        }
+        state.last_delivered = delivered.clone();
+        delivered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    fn exact_channels(count: usize) -> Vec<ChannelNoise> {
+        vec![ChannelNoise::default(); count]
+    }
+
+    #[test]
+    fn latency_repeats_the_first_reading_then_delivers_late() {
+        let sensors = SensorConfig { channels: exact_channels(1), latency_ticks: 2, dropout: 0. };
+        let mut state = SensorState::default();
+        let delivered: Vec<f32> = (0..6).map(|tick| sensors.observe(&mut state, vec![tick as f32], 0.1)[0]).collect();
+        assert_eq!(delivered, vec![0., 0., 0., 1., 2., 3.]);
+
+        let sensors = SensorConfig { latency_ticks: 1, ..sensors };
+        let mut state = SensorState::default();
+        let delivered: Vec<f32> = (0..4).map(|tick| sensors.observe(&mut state, vec![tick as f32], 0.1)[0]).collect();
+        assert_eq!(delivered, vec![0., 0., 1., 2.]);
+    }
+
+    #[test]
+    fn dropped_readings_keep_the_last_delivered_one() {
+        let sensors = SensorConfig { channels: exact_channels(2), latency_ticks: 0, dropout: 1. };
+        let mut state = SensorState::default();
+        // Nothing was delivered before the first reading
+        assert_eq!(sensors.observe(&mut state, vec![1., 2.], 0.1), vec![1., 2.]);
+        assert_eq!(sensors.observe(&mut state, vec![3., 4.], 0.1), vec![1., 2.]);
+
+        let sensors = SensorConfig { dropout: 0.5, ..sensors };
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut state = SensorState::default();
+        sensors.observe_with(&mut rng, &mut state, vec![0., 0.], 0.1);
+        let dropped = (1..1001).filter(|tick| sensors.observe_with(&mut rng, &mut state, vec![*tick as f32; 2], 0.1)[0] != *tick as f32).count();
+        assert!((400..600).contains(&dropped), "{} of 1000 dropped", dropped);
+    }
+
+    #[test]
+    fn readings_are_quantized_and_extra_channels_are_exact() {
+        let sensors = SensorConfig {
+            channels: vec![ChannelNoise::new(0., 0., 0.25)],
+            latency_ticks: 0,
+            dropout: 0.,
+        };
+        let mut state = SensorState::default();
+        assert_eq!(sensors.observe(&mut state, vec![0.3, 0.3], 0.1), vec![0.25, 0.3]);
+        assert_eq!(sensors.observe(&mut state, vec![-0.4, 0.3], 0.1), vec![-0.5, 0.3]);
+    }
+
+    #[test]
+    fn bias_drifts_by_its_deviation_per_second() {
+        let sensors = SensorConfig {
+            channels: vec![ChannelNoise::new(0., 0.5, 0.)],
+            latency_ticks: 0,
+            dropout: 0.,
+        };
+        let mut rng = StdRng::seed_from_u64(11);
+        let dt = 0.1;
+        // Many sensors with a constant truth, the spread of their readings after one second is the drift
+        let readings: Vec<f32> = (0..2000).map(|_| {
+            let mut state = SensorState::default();
+            let mut reading = 0.;
+            for _ in 0..10 {
+                reading = sensors.observe_with(&mut rng, &mut state, vec![1.], dt)[0];
+            }
+            reading - 1.
+        }).collect();
+        let variance = readings.iter().map(|reading| reading * reading).sum::<f32>() / readings.len() as f32;
+        assert!((variance.sqrt() - 0.5).abs() < 0.05, "deviation {}", variance.sqrt());
+
+        // The same seed gives the same readings
+        let mut first = SensorState::default();
+        let mut second = SensorState::default();
+        let a = sensors.observe_with(&mut StdRng::seed_from_u64(3), &mut first, vec![1.], dt);
+        let b = sensors.observe_with(&mut StdRng::seed_from_u64(3), &mut second, vec![1.], dt);
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn perfect_sensors_pass_the_truth_through() {
+        let mut state = SensorState::default();
+        assert_eq!(SensorConfig::default().observe(&mut state, vec![0.123, -4.], 0.1), vec![0.123, -4.]);
+    }
+}
@@ -0,0 +1,240 @@
+use std::fs;
+use crate::point::Vector;
+
+#[derive(Debug, Clone)]
+pub struct Thruster {
+    // Index of the point the thruster is mounted on
+    pub point: usize,
+    // The gimbal turns at most this far to either side, in radians
+    pub gimbal_range: f32,
+    // Thrust at full throttle as a multiple of `PhysicsConfig::thrust_acceleration`
+    pub max_thrust: f32,
+}
+
+// Layout of a ship: equal point masses, rigid links between them and thrusters on them.
+// The first two points define the ship axis, it points from the second to the first one.
+#[derive(Debug, Clone)]
+pub struct ShipDesign {
+    // Rest positions in metres, relative to the start position of the ship
+    pub points: Vec<Vector>,
+    // Pairs of points held at their rest distance
+    pub links: Vec<(usize, usize)>,
+    pub thrusters: Vec<Thruster>,
+}
+
+impl Default for ShipDesign {
+    fn default() -> ShipDesign {
+        ShipDesign::twin()
+    }
+}
+
+impl ShipDesign {
+    // The original ship: a one metre rod with a motor at each end
+    pub fn twin() -> ShipDesign {
+        ShipDesign {
+            points: vec![Vector::new(0.5, 0.), Vector::new(-0.5, 0.)],
+            links: vec![(0, 1)],
+            thrusters: vec![
+                Thruster { point: 0, gimbal_range: 1., max_thrust: 1. },
+                Thruster { point: 1, gimbal_range: 1., max_thrust: 1. },
+            ],
+        }
+    }
+
+    // Triangle with one strong engine below the middle
+    pub fn single_engine() -> ShipDesign {
+        ShipDesign {
+            points: vec![Vector::new(0.4, 0.), Vector::new(-0.4, 0.), Vector::new(0., 0.3)],
+            links: vec![(0, 1), (1, 2), (2, 0)],
+            thrusters: vec![Thruster { point: 2, gimbal_range: 0.5, max_thrust: 2.5 }],
+        }
+    }
+
+    // Four motors along a bar, like a quadcopter seen from the side
+    pub fn quad() -> ShipDesign {
+        ShipDesign {
+            points: vec![
+                Vector::new(0.75, 0.),
+                Vector::new(-0.75, 0.),
+                Vector::new(0.25, 0.),
+                Vector::new(-0.25, 0.),
+            ],
+            links: vec![(0, 1), (0, 2), (0, 3), (1, 2), (1, 3), (2, 3)],
+            thrusters: (0..4).map(|point| Thruster { point, gimbal_range: 0.3, max_thrust: 1. }).collect(),
+        }
+    }
+
+    // Off center engines of different strength
+    pub fn asymmetric() -> ShipDesign {
+        ShipDesign {
+            points: vec![Vector::new(0.6, 0.), Vector::new(-0.4, 0.), Vector::new(0.1, 0.35)],
+            links: vec![(0, 1), (1, 2), (2, 0)],
+            thrusters: vec![
+                Thruster { point: 1, gimbal_range: 0.6, max_thrust: 1.2 },
+                Thruster { point: 2, gimbal_range: 0.2, max_thrust: 1.8 },
+            ],
+        }
+    }
+
+    // A preset name or the path of a design file
+    pub fn named_or_loaded(name: &str) -> Result<ShipDesign, String> {
+        match name {
+            "twin" => Ok(ShipDesign::twin()),
+            "single" => Ok(ShipDesign::single_engine()),
+            "quad" => Ok(ShipDesign::quad()),
+            "asymmetric" => Ok(ShipDesign::asymmetric()),
+            path => ShipDesign::load(path),
+        }
+    }
+
+    pub fn load(path: &str) -> Result<ShipDesign, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        ShipDesign::from_text(&text)
+    }
+
+    // One item per line, # starts a comment:
+    //   point <x> <y>
+    //   link <point> <point>
+    //   thruster <point> <gimbal range> <max thrust>
+    pub fn from_text(text: &str) -> Result<ShipDesign, String> {
+        let mut design = ShipDesign { points: vec![], links: vec![], thrusters: vec![] };
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let error = || format!("line {}: could not read \"{}\"", number + 1, line);
+            let float = |i: usize| words.get(i).and_then(|word| word.parse::<f32>().ok()).ok_or_else(error);
+            let index = |i: usize| words.get(i).and_then(|word| word.parse::<usize>().ok()).ok_or_else(error);
+            match words[0] {
+                "point" => design.points.push(Vector::new(float(1)?, float(2)?)),
+                "link" => design.links.push((index(1)?, index(2)?)),
+                "thruster" => design.thrusters.push(Thruster {
+                    point: index(1)?,
+                    gimbal_range: float(2)?,
+                    max_thrust: float(3)?,
+                }),
+                _ => return Err(error()),
+            }
+        }
+        design.validate()?;
+        Ok(design)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.points.len() < 2 {
+            return Err("a ship needs at least two points".to_string());
+        }
+        if self.points[0].subtracted(&self.points[1]).length() == 0. {
+            return Err("the first two points define the ship axis and must differ".to_string());
+        }
+        if self.thrusters.is_empty() {
+            return Err("a ship needs at least one thruster".to_string());
+        }
+        let point_count = self.points.len();
+        for (a, b) in &self.links {
+            if *a >= point_count || *b >= point_count || a == b {
+                return Err(format!("link {} {} does not join two points", a, b));
+            }
+            // A link without length cannot hold the points apart
+            if self.rest_length(&(*a, *b)) == 0. {
+                return Err(format!("link {} {} joins two points at the same position", a, b));
+            }
+        }
+        for thruster in &self.thrusters {
+            if thruster.point >= point_count {
+                return Err(format!("thruster on missing point {}", thruster.point));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn rest_length(&self, link: &(usize, usize)) -> f32 {
+        self.points[link.0].subtracted(&self.points[link.1]).length()
+    }
+
+    // `Vector::angle` of the ship axis as designed, the heading of a ship that is not tilted
+    pub fn rest_heading(&self) -> f32 {
+        self.points[0].subtracted(&self.points[1]).angle()
+    }
+
+    pub fn centroid(&self) -> Vector {
+        let sum = self.points.iter().fold(Vector::new(0., 0.), |sum, point| sum.added(point));
+        sum.multiplied(1. / self.points.len() as f32)
+    }
+
+    // Rest points relative to the centroid, turned so the ship axis lies along x
+    pub fn local_points(&self) -> Vec<Vector> {
+        let axis = self.points[0].subtracted(&self.points[1]);
+        let (sin, cos) = (-axis.y.atan2(axis.x)).sin_cos();
+        let centroid = self.centroid();
+        self.points.iter().map(|point| {
+            let offset = point.subtracted(&centroid);
+            Vector::new(offset.x * cos - offset.y * sin, offset.x * sin + offset.y * cos)
+        }).collect()
+    }
+
+    // Moment of inertia of the point masses around the centroid
+    pub fn inertia(&self, mass: f32) -> f32 {
+        let point_mass = mass / self.points.len() as f32;
+        let centroid = self.centroid();
+        self.points.iter().map(|point| point_mass * point.subtracted(&centroid).length().powi(2)).sum()
+    }
+
+    // Throttle and gimbal angle per thruster
+    pub fn output_size(&self) -> usize {
+        self.thrusters.len() * 2
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn design_file_is_read_with_comments_and_blank_lines() {
+        let text = "# A rod\npoint 0.5 0\npoint -0.5 0   # tail\n\nlink 0 1\nthruster 1 0.25 1.5\n";
+        let design = ShipDesign::from_text(text).unwrap();
+        assert_eq!(design.points.len(), 2);
+        assert_eq!((design.points[1].x, design.points[1].y), (-0.5, 0.));
+        assert_eq!(design.links, vec![(0, 1)]);
+        assert_eq!(design.thrusters.len(), 1);
+        assert_eq!((design.thrusters[0].point, design.thrusters[0].gimbal_range, design.thrusters[0].max_thrust), (1, 0.25, 1.5));
+    }
+
+    #[test]
+    fn unreadable_lines_name_their_line_number() {
+        let base = "point 0.5 0\npoint -0.5 0\nthruster 0 1 1\n";
+        for line in ["pointy 1 2", "point 1", "point 1 x", "link 0 -1", "thruster 0 1"] {
+            let error = ShipDesign::from_text(&format!("{}{}", base, line)).unwrap_err();
+            assert!(error.starts_with("line 4:"), "{} gave {}", line, error);
+        }
+    }
+
+    #[test]
+    fn invalid_designs_are_rejected() {
+        let rod = "point 0.5 0\npoint -0.5 0\n";
+        let cases = [
+            "point 0 0\nthruster 0 1 1",
+            "point 1 1\npoint 1 1\nthruster 0 1 1",
+            &format!("{}link 0 1", rod),
+            &format!("{}thruster 2 1 1", rod),
+            &format!("{}thruster 0 1 1\nlink 0 2", rod),
+            &format!("{}thruster 0 1 1\nlink 1 1", rod),
+            // Zero length link between two distinct points
+            &format!("{}point 0.5 0\nthruster 0 1 1\nlink 0 2", rod),
+        ];
+        for case in cases {
+            assert!(ShipDesign::from_text(case).is_err(), "accepted {:?}", case);
+        }
+        assert!(ShipDesign::from_text(&format!("{}point 0.5 0\nthruster 0 1 1\nlink 1 2", rod)).is_ok());
+    }
+
+    #[test]
+    fn presets_are_valid() {
+        for name in ["twin", "single", "quad", "asymmetric"] {
+            assert!(ShipDesign::named_or_loaded(name).unwrap().validate().is_ok(), "{}", name);
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+// When to end training. Scores are per second of a fixed-length episode, lower is better.
+pub struct StoppingCriteria {
+    pub max_generations: u32,
+    // Stop once the best score of a generation is at or below this
+    pub target_score: Option<f32>,
+    // Stop after this many generations without the best score improving by min_improvement
+    pub patience: Option<u32>,
+    pub min_improvement: f32,
+    pub time_budget: Option<Duration>,
+    // Stop when the average distance between networks falls below this
+    pub min_diversity: Option<f32>,
+}
+
+impl Default for StoppingCriteria {
+    fn default() -> StoppingCriteria {
+        StoppingCriteria {
+            max_generations: 100,
+            target_score: None,
+            patience: Some(30),
+            min_improvement: 0.001,
+            time_budget: Some(Duration::from_secs(60 * 60)),
+            min_diversity: Some(0.01),
+        }
+    }
+}
+
+impl StoppingCriteria {
+    // Reads a "--<option>=<value>" argument, None when it is not a stopping option. "off"
+    // turns off the optional criteria, the time budget is in seconds.
+    pub fn set(&mut self, arg: &str) -> Option<Result<(), String>> {
+        let (option, value) = arg.strip_prefix("--")?.split_once('=')?;
+        let error = || format!("could not read {} for --{}", value, option);
+        let number = || value.parse::<f32>().map_err(|_| error());
+        let optional = || if value == "off" { Ok(None) } else { number().map(Some) };
+        let result = match option {
+            "max-generations" => value.parse().map(|generations| self.max_generations = generations).map_err(|_| error()),
+            "target-score" => optional().map(|target| self.target_score = target),
+            "patience" if value == "off" => {
+                self.patience = None;
+                Ok(())
+            },
+            "patience" => value.parse().map(|patience| self.patience = Some(patience)).map_err(|_| error()),
+            "min-improvement" => number().map(|improvement| self.min_improvement = improvement),
+            "time-budget" => optional().map(|seconds| self.time_budget = seconds.map(Duration::from_secs_f32)),
+            "min-diversity" => optional().map(|diversity| self.min_diversity = diversity),
+            _ => return None,
+        };
+        Some(result)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum StopReason {
+    MaxGenerations(u32),
+    TargetReached(f32),
+    NoImprovement(u32),
+    TimeBudget(Duration),
+    DiversityCollapse(f32),
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StopReason::MaxGenerations(generations) => write!(f, "reached the maximum of {} generations", generations),
+            StopReason::TargetReached(score) => write!(f, "target score reached with {}", score),
+            StopReason::NoImprovement(generations) => write!(f, "no improvement in {} generations", generations),
+            StopReason::TimeBudget(elapsed) => write!(f, "time budget used up after {:.1}s", elapsed.as_secs_f32()),
+            StopReason::DiversityCollapse(diversity) => write!(f, "population diversity collapsed to {}", diversity),
+        }
+    }
+}
+
+pub struct TrainingMonitor {
+    pub criteria: StoppingCriteria,
+    started: Instant,
+    best_score: f32,
+    generations_without_improvement: u32,
+    generations_checked: u32,
+}
+
+impl TrainingMonitor {
+    pub fn new(criteria: StoppingCriteria) -> TrainingMonitor {
+        TrainingMonitor {
+            criteria,
+            started: Instant::now(),
+            best_score: f32::MAX,
+            generations_without_improvement: 0,
+            generations_checked: 0,
+        }
+    }
+
+    // Called once per generation after evaluation. Returns why training should stop, if it should.
+    pub fn check(&mut self, best_score: f32, diversity: Option<f32>) -> Option<StopReason> {
+        self.generations_checked += 1;
+        // The reference stays at the last score that improved enough, so slow progress adds up
+        if best_score < self.best_score - self.criteria.min_improvement {
+            self.best_score = best_score;
+            self.generations_without_improvement = 0;
+        } else {
+            self.generations_without_improvement += 1;
+        }
+
+        if let Some(target) = self.criteria.target_score {
+            if best_score <= target {
+                return Some(StopReason::TargetReached(best_score));
+            }
+        }
+        if let Some(patience) = self.criteria.patience {
+            if self.generations_without_improvement >= patience {
+                return Some(StopReason::NoImprovement(self.generations_without_improvement));
+            }
+        }
+        if let Some(budget) = self.criteria.time_budget {
+            let elapsed = self.started.elapsed();
+            if elapsed >= budget {
+                return Some(StopReason::TimeBudget(elapsed));
+            }
+        }
+        // The first generation has not been bred yet, an initializer like zeros starts with
+        // identical networks on purpose
+        if let (Some(min_diversity), Some(diversity), true) = (self.criteria.min_diversity, diversity, self.generations_checked > 1) {
+            if diversity < min_diversity {
+                return Some(StopReason::DiversityCollapse(diversity));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn monitor() -> TrainingMonitor {
+        TrainingMonitor::new(StoppingCriteria {
+            patience: Some(3),
+            min_improvement: 0.1,
+            time_budget: None,
+            min_diversity: None,
+            ..StoppingCriteria::default()
+        })
+    }
+
+    #[test]
+    fn slow_progress_adds_up() {
+        let mut monitor = monitor();
+        // 0.06 per generation is below min_improvement, every second step crosses it
+        for generation in 0..20 {
+            let score = 10. - generation as f32 * 0.06;
+            assert!(monitor.check(score, None).is_none(), "stopped at generation {}", generation);
+        }
+    }
+
+    #[test]
+    fn target_score_stops_training() {
+        let mut criteria = StoppingCriteria::default();
+        assert!(criteria.set("--target-score=0.5").unwrap().is_ok());
+        let mut monitor = TrainingMonitor::new(criteria);
+        assert!(monitor.check(0.6, None).is_none());
+        assert!(matches!(monitor.check(0.5, None), Some(StopReason::TargetReached(_))));
+    }
+
+    #[test]
+    fn options_are_read_from_arguments() {
+        let mut criteria = StoppingCriteria::default();
+        for arg in ["--max-generations=7", "--patience=off", "--min-improvement=0.5", "--time-budget=90", "--min-diversity=off"] {
+            assert!(criteria.set(arg).unwrap().is_ok(), "{}", arg);
+        }
+        assert_eq!(criteria.max_generations, 7);
+        assert_eq!(criteria.patience, None);
+        assert_eq!(criteria.min_improvement, 0.5);
+        assert_eq!(criteria.time_budget, Some(Duration::from_secs(90)));
+        assert_eq!(criteria.min_diversity, None);
+
+        assert!(criteria.set("--patience=3").unwrap().is_ok());
+        assert_eq!(criteria.patience, Some(3));
+        assert!(criteria.set("--patience=soon").unwrap().is_err());
+        assert!(criteria.set("--max-generations=off").unwrap().is_err());
+        assert!(criteria.set("--init=zeros").is_none());
+        assert!(criteria.set("--rigid-body").is_none());
+    }
+
+    #[test]
+    fn identical_first_generation_is_not_a_collapse() {
+        let mut monitor = TrainingMonitor::new(StoppingCriteria::default());
+        assert!(monitor.check(10., Some(0.)).is_none());
+        assert!(matches!(monitor.check(9., Some(0.)), Some(StopReason::DiversityCollapse(_))));
+    }
+
+    #[test]
+    fn stagnation_stops_after_patience() {
+        let mut monitor = monitor();
+        assert!(monitor.check(10., None).is_none());
+        assert!(monitor.check(9.95, None).is_none());
+        assert!(monitor.check(9.95, None).is_none());
+        assert!(matches!(monitor.check(9.95, None), Some(StopReason::NoImprovement(3))));
+    }
+}
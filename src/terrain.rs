@@ -0,0 +1,209 @@
+use crate::point::Vector;
+
+// Flat stretch of ground the ships should land on
+#[derive(Debug, Clone)]
+pub struct LandingPad {
+    pub left: f32,
+    pub right: f32,
+}
+
+impl LandingPad {
+    pub fn contains(&self, x: f32) -> bool {
+        x >= self.left && x <= self.right
+    }
+
+    pub fn center_x(&self) -> f32 {
+        (self.left + self.right) * 0.5
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TouchdownKind {
+    Landed,
+    Crashed,
+}
+
+// What happened when the ship first touched the ground
+#[derive(Debug, Clone)]
+pub struct Touchdown {
+    pub kind: TouchdownKind,
+    // Speed of the ship center in m/s and tilt from the design orientation in radians at the
+    // moment of impact
+    pub speed: f32,
+    pub tilt: f32,
+    // Pad the ship center was over, if any
+    pub pad: Option<usize>,
+}
+
+impl Touchdown {
+    pub fn landed_on(&self, pad: usize) -> bool {
+        self.kind == TouchdownKind::Landed && self.pad == Some(pad)
+    }
+}
+
+// Ground as a height map. Like the rest of the world y points down, so a point is below
+// the ground when its y is larger than the height at its x.
+#[derive(Debug, Clone)]
+pub struct Terrain {
+    // Polyline sorted by x, the first and last heights extend to infinity
+    pub ground: Vec<Vector>,
+    pub pads: Vec<LandingPad>,
+    // Pad the landing task aims for
+    pub target_pad: usize,
+    // Touching down faster or more tilted than this is a crash
+    pub max_landing_speed: f32,
+    pub max_landing_tilt: f32,
+}
+
+impl Terrain {
+    // Hilly ground with one pad to the right of the start and one to the left
+    pub fn lunar() -> Terrain {
+        let ground = vec![
+            Vector::new(-6., 2.2),
+            Vector::new(-4.5, 2.8),
+            Vector::new(-3.5, 3.2),
+            Vector::new(-2.5, 3.2),
+            Vector::new(-1.5, 2.6),
+            Vector::new(-0.5, 3.4),
+            Vector::new(0.5, 3.),
+            Vector::new(1., 3.),
+            Vector::new(2.5, 3.),
+            Vector::new(3.2, 2.4),
+            Vector::new(4.2, 2.9),
+            Vector::new(6., 2.),
+        ];
+        Terrain {
+            ground,
+            pads: vec![
+                LandingPad { left: 1., right: 2.5 },
+                LandingPad { left: -3.5, right: -2.5 },
+            ],
+            target_pad: 0,
+            max_landing_speed: 1.5,
+            max_landing_tilt: 0.3,
+        }
+    }
+
+    pub fn height_at(&self, x: f32) -> f32 {
+        let first = &self.ground[0];
+        let last = &self.ground[self.ground.len() - 1];
+        if x <= first.x {
+            return first.y;
+        }
+        if x >= last.x {
+            return last.y;
+        }
+        for segment in self.ground.windows(2) {
+            let (a, b) = (&segment[0], &segment[1]);
+            if x <= b.x {
+                let t = (x - a.x) / (b.x - a.x);
+                return a.y + (b.y - a.y) * t;
+            }
+        }
+        last.y
+    }
+
+    // The ground line as segments, with the flat ends stretched far beyond the world
+    pub fn segments(&self) -> Vec<(Vector, Vector)> {
+        let first = &self.ground[0];
+        let last = &self.ground[self.ground.len() - 1];
+        let mut segments = vec![(Vector::new(first.x - 1000., first.y), first.clone())];
+        for segment in self.ground.windows(2) {
+            segments.push((segment[0].clone(), segment[1].clone()));
+        }
+        segments.push((last.clone(), Vector::new(last.x + 1000., last.y)));
+        segments
+    }
+
+    // How far the point is below the ground, 0 when it is above
+    pub fn penetration(&self, point: &Vector) -> f32 {
+        (point.y - self.height_at(point.x)).max(0.)
+    }
+
+    pub fn pad_at(&self, x: f32) -> Option<usize> {
+        self.pads.iter().position(|pad| pad.contains(x))
+    }
+
+    pub fn pad_center(&self, index: usize) -> Vector {
+        let x = self.pads[index].center_x();
+        Vector::new(x, self.height_at(x))
+    }
+
+    pub fn classify(&self, center_x: f32, speed: f32, tilt: f32) -> Touchdown {
+        let kind = if speed <= self.max_landing_speed && tilt.abs() <= self.max_landing_tilt {
+            TouchdownKind::Landed
+        } else {
+            TouchdownKind::Crashed
+        };
+        Touchdown {
+            kind,
+            speed,
+            tilt,
+            pad: self.pad_at(center_x),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 1e-5, "{} instead of {}", actual, expected);
+    }
+
+    #[test]
+    fn heights_are_interpolated_between_vertices() {
+        let terrain = Terrain::lunar();
+        assert_close(terrain.height_at(-4.5), 2.8);
+        // Halfway between (-6, 2.2) and (-4.5, 2.8), and a quarter of the way down from (-1.5, 2.6) to (-0.5, 3.4)
+        assert_close(terrain.height_at(-5.25), 2.5);
+        assert_close(terrain.height_at(-1.25), 2.8);
+        // Flat beyond both ends
+        assert_close(terrain.height_at(-100.), 2.2);
+        assert_close(terrain.height_at(100.), 2.);
+    }
+
+    #[test]
+    fn penetration_is_the_depth_below_the_ground() {
+        let terrain = Terrain::lunar();
+        assert_eq!(terrain.penetration(&Vector::new(1.5, 2.)), 0.);
+        assert_close(terrain.penetration(&Vector::new(1.5, 3.25)), 0.25);
+        assert_close(terrain.penetration(&Vector::new(-5.25, 2.6)), 0.1);
+    }
+
+    #[test]
+    fn pads_are_found_by_x_including_their_edges() {
+        let terrain = Terrain::lunar();
+        assert_eq!(terrain.pad_at(1.), Some(0));
+        assert_eq!(terrain.pad_at(2.5), Some(0));
+        assert_eq!(terrain.pad_at(-3.), Some(1));
+        assert_eq!(terrain.pad_at(0.), None);
+        assert_eq!(terrain.pad_at(2.51), None);
+        let center = terrain.pad_center(0);
+        assert_close(center.x, 1.75);
+        assert_close(center.y, 3.);
+    }
+
+    #[test]
+    fn touchdowns_are_judged_by_speed_and_tilt() {
+        let terrain = Terrain::lunar();
+        let on_pad = terrain.classify(1.75, 1.5, -0.3);
+        assert_eq!(on_pad.kind, TouchdownKind::Landed);
+        assert!(on_pad.landed_on(0) && !on_pad.landed_on(1));
+
+        assert_eq!(terrain.classify(1.75, 1.51, 0.).kind, TouchdownKind::Crashed);
+        assert_eq!(terrain.classify(1.75, 0., 0.31).kind, TouchdownKind::Crashed);
+        assert_eq!(terrain.classify(1.75, 0., -0.31).kind, TouchdownKind::Crashed);
+
+        // A soft touchdown on the slope is a landing but not on any pad
+        let on_slope = terrain.classify(0., 0.5, 0.1);
+        assert_eq!(on_slope.kind, TouchdownKind::Landed);
+        assert_eq!(on_slope.pad, None);
+        assert!(!on_slope.landed_on(terrain.target_pad));
+
+        let crashed_on_pad = terrain.classify(2., 3., 0.);
+        assert_eq!(crashed_on_pad.pad, Some(0));
+        assert!(!crashed_on_pad.landed_on(0));
+    }
+}
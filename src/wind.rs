@@ -0,0 +1,172 @@
+use crate::point::Vector;
+
+#[derive(Debug, Clone)]
+pub enum WindField {
+    Calm,
+    Constant(Vector),
+    // Base wind plus a sinusoidal gust along the same direction
+    Gusting { base: Vector, gust_strength: f32, gust_period: f32 },
+    // Base wind plus smooth noise in space and time
+    Turbulence { base: Vector, strength: f32, length_scale: f32, time_scale: f32 },
+    // Wind that grows linearly with height above y = 0 (y points down)
+    Shear { base: Vector, per_metre: Vector },
+}
+
+// Drag and wind applied to both ship points
+#[derive(Debug, Clone)]
+pub struct AeroConfig {
+    // Drag acceleration is -(linear_drag * v + quadratic_drag * |v| * v), v relative to the air
+    pub linear_drag: f32,
+    pub quadratic_drag: f32,
+    pub wind: WindField,
+}
+
+impl Default for AeroConfig {
+    fn default() -> AeroConfig {
+        AeroConfig {
+            linear_drag: 0.,
+            quadratic_drag: 0.,
+            wind: WindField::Calm,
+        }
+    }
+}
+
+impl AeroConfig {
+    // Drag with the named wind field, see `WindField::named`
+    pub fn windy(wind_name: &str) -> Option<AeroConfig> {
+        Some(AeroConfig {
+            linear_drag: 0.1,
+            quadratic_drag: 0.05,
+            wind: WindField::named(wind_name)?,
+        })
+    }
+
+    pub fn drag_acceleration(&self, position: &Vector, velocity: &Vector, time: f32) -> Vector {
+        let relative = velocity.subtracted(&self.wind.velocity_at(position, time));
+        let speed = relative.length();
+        relative.multiplied(-(self.linear_drag + self.quadratic_drag * speed))
+    }
+}
+
+// Deterministic pseudo random value in [-1, 1] for an integer lattice point
+fn lattice_value(x: i32, y: i32, z: i32, seed: u32) -> f32 {
+    let mut h = (x as u32).wrapping_mul(0x8da6b343)
+        ^ (y as u32).wrapping_mul(0xd8163841)
+        ^ (z as u32).wrapping_mul(0xcb1ab31f)
+        ^ seed.wrapping_mul(0x165667b1);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0x5bd1e995);
+    h ^= h >> 15;
+    (h as f32 / u32::MAX as f32) * 2. - 1.
+}
+
+fn smooth(t: f32) -> f32 {
+    t * t * (3. - 2. * t)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+// Smoothly interpolated value noise in three dimensions, in [-1, 1]
+fn value_noise(x: f32, y: f32, z: f32, seed: u32) -> f32 {
+    let (x0, y0, z0) = (x.floor(), y.floor(), z.floor());
+    let (tx, ty, tz) = (smooth(x - x0), smooth(y - y0), smooth(z - z0));
+    let (xi, yi, zi) = (x0 as i32, y0 as i32, z0 as i32);
+
+    let corner = |dx: i32, dy: i32, dz: i32| lattice_value(xi + dx, yi + dy, zi + dz, seed);
+    let plane = |dz: i32| {
+        lerp(
+            lerp(corner(0, 0, dz), corner(1, 0, dz), tx),
+            lerp(corner(0, 1, dz), corner(1, 1, dz), tx),
+            ty,
+        )
+    };
+    lerp(plane(0), plane(1), tz)
+}
+
+impl WindField {
+    // Presets that can be picked from the command line
+    pub fn named(name: &str) -> Option<WindField> {
+        match name {
+            "calm" => Some(WindField::Calm),
+            "constant" => Some(WindField::Constant(Vector::new(1.5, 0.))),
+            "gusts" => Some(WindField::Gusting {
+                base: Vector::new(1., 0.),
+                gust_strength: 3.,
+                gust_period: 4.,
+            }),
+            "turbulence" => Some(WindField::Turbulence {
+                base: Vector::new(1.5, 0.),
+                strength: 2.,
+                length_scale: 2.,
+                time_scale: 1.5,
+            }),
+            "shear" => Some(WindField::Shear {
+                base: Vector::new(0.5, 0.),
+                per_metre: Vector::new(1., 0.),
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn velocity_at(&self, position: &Vector, time: f32) -> Vector {
+        match self {
+            WindField::Calm => Vector::new(0., 0.),
+            WindField::Constant(wind) => wind.clone(),
+            WindField::Gusting { base, gust_strength, gust_period } => {
+                let gust = (time * 2. * std::f32::consts::PI / gust_period).sin().max(0.);
+                base.added(&base.normalized().multiplied(gust * gust_strength))
+            },
+            WindField::Turbulence { base, strength, length_scale, time_scale } => {
+                let x = position.x / length_scale;
+                let y = position.y / length_scale;
+                let t = time / time_scale;
+                base.added(&Vector::new(
+                    value_noise(x, y, t, 1) * strength,
+                    value_noise(x, y, t, 2) * strength,
+                ))
+            },
+            WindField::Shear { base, per_metre } => base.added(&per_metre.multiplied(-position.y)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quadratic(wind: WindField) -> AeroConfig {
+        AeroConfig { linear_drag: 0., quadratic_drag: 0.5, wind }
+    }
+
+    #[test]
+    fn drag_opposes_the_airflow() {
+        let aero = quadratic(WindField::Constant(Vector::new(1., 0.)));
+        let origin = Vector::new(0., 0.);
+        // Moving right at 3 into a 1 m/s tailwind leaves 2 m/s of airflow from the right
+        let drag = aero.drag_acceleration(&origin, &Vector::new(3., 0.), 0.);
+        assert!((drag.x + 0.5 * 2. * 2.).abs() < 1e-6 && drag.y.abs() < 1e-6);
+        // Standing still, the wind pushes the ship along with it
+        let push = aero.drag_acceleration(&origin, &Vector::new(0., 0.), 0.);
+        assert!(push.x > 0. && push.y.abs() < 1e-6);
+        // Moving with the wind there is no drag
+        assert!(aero.drag_acceleration(&origin, &Vector::new(1., 0.), 0.).length() < 1e-6);
+    }
+
+    #[test]
+    fn quadratic_drag_scales_with_speed_squared() {
+        let aero = quadratic(WindField::Calm);
+        let origin = Vector::new(0., 0.);
+        let slow = aero.drag_acceleration(&origin, &Vector::new(0., 1.), 0.).length();
+        let fast = aero.drag_acceleration(&origin, &Vector::new(0., 3.), 0.).length();
+        assert!((fast / slow - 9.).abs() < 1e-4, "ratio {}", fast / slow);
+    }
+
+    #[test]
+    fn calm_air_at_rest_has_no_drag() {
+        let aero = AeroConfig { wind: WindField::Calm, ..AeroConfig::windy("calm").unwrap() };
+        let drag = aero.drag_acceleration(&Vector::new(2., -3.), &Vector::new(0., 0.), 5.);
+        assert_eq!(drag.length(), 0.);
+    }
+}